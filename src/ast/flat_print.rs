@@ -109,9 +109,7 @@ struct WrappingBuffer {
 }
 
 impl<T: Into<PrintItem>> AddAssign<T> for WrappingBuffer {
-    fn add_assign(&mut self, rhs: T) {
-        use PrintItem::*;
-    }
+    fn add_assign(&mut self, _rhs: T) {}
 }
 
 struct PrintLayoutEngine {}
@@ -1,8 +1,8 @@
+mod flat_print;
 mod parse;
 mod print;
-mod wadler_print;
-mod flat_print;
 mod types;
+mod wadler_print;
 
 pub use parse::parse;
 pub use types::{AstNode, AstNodeKind, AstNodeRef, AstNodeRefMut, AstNodeVec};
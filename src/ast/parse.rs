@@ -1,10 +1,15 @@
 use super::*;
 use crate::tokens::*;
+use crate::util::Symbols;
 
 #[derive(Clone, Copy, Debug)]
 struct StackState {
     start_token: u32,
     start_tree_index: u32,
+    /// Binary operators that bind looser than this end the expression.
+    min_prec: u8,
+    /// The operator node to add once its operands are parsed.
+    op: Option<AstNodeKind>,
 }
 
 type ParseStackFunc = fn(ctx: &mut ParseContext, state: StackState) -> Result<(), String>;
@@ -20,9 +25,33 @@ struct ParseContext<'a> {
     index: usize,
     parse_stack: Vec<StackEntry>,
     tree: AstNodeVec,
+    /// How many functions the parser is inside of, for `return`.
+    function_depth: u32,
+    /// How many `>`s are left of a `>>` or `>>>` token that closed nested
+    /// type arguments, as in `A<B<C>>`.
+    split_gts: u32,
+    /// Whether there's an `=` left after those, from a `>=`, `>>=` or `>>>=`,
+    /// as in `let a: A<B>= c`.
+    split_eq: bool,
+    /// The symbol for `target`, for `new.target`, or `None` if no word in the
+    /// source has it.
+    target_symbol: Option<u32>,
+    /// Where the true branch of each conditional that's being parsed starts,
+    /// innermost last, for `arrow_after_return_type`.
+    conditionals: Vec<usize>,
 }
 
 impl<'a> ParseContext<'a> {
+    // A state for a node starting at the next token.
+    fn here(&self) -> StackState {
+        return StackState {
+            start_token: self.index as u32,
+            start_tree_index: self.tree.len() as u32,
+            min_prec: 0,
+            op: None,
+        };
+    }
+
     fn add_node(&mut self, state: &StackState, kind: AstNodeKind) {
         self.add_node_extra(state, kind, 0);
     }
@@ -39,7 +68,7 @@ impl<'a> ParseContext<'a> {
         self.index += 1;
     }
 
-    fn consume_if(&mut self, kind: TokenKind) -> Option<TokenRef> {
+    fn consume_if(&mut self, kind: TokenKind) -> Option<TokenRef<'_>> {
         let token = self.tokens.get(self.index)?;
         if *token.kind != kind {
             return None;
@@ -53,20 +82,17 @@ impl<'a> ParseContext<'a> {
     fn consume_spaces(&mut self) -> usize {
         let start = self.index;
         while let Some(token) = self.tokens.get(self.index) {
-            let token_kind = *token.kind;
-            match token_kind {
-                TokenKind::Whitespace | TokenKind::Comment | TokenKind::LineComment => {
-                    self.incr();
-                    continue;
-                }
-                _ => break,
+            if !token.kind.is_trivia() {
+                break;
             }
+
+            self.incr();
         }
 
         return self.index - start;
     }
 
-    fn consume_ifs(&mut self, kinds: &[TokenKind]) -> Option<TokenRef> {
+    fn consume_ifs(&mut self, kinds: &[TokenKind]) -> Option<TokenRef<'_>> {
         let token = self.tokens.get(self.index)?;
         let token_kind = *token.kind;
         for &kind in kinds {
@@ -79,11 +105,285 @@ impl<'a> ParseContext<'a> {
         return None;
     }
 
+    // Whether there's a line break before the next token, for ASI and the
+    // restricted productions like `return\nx` and `a\n++b`.
+    fn newline_before(&self) -> bool {
+        return self
+            .tokens
+            .get(self.index)
+            .is_some_and(|token| *token.newline_before);
+    }
+
+    // Ends a statement, either with a `;` or with an automatically inserted
+    // one, which needs a line break, a `}` or the end of the file.
+    fn consume_semicolon(&mut self) -> Result<(), String> {
+        self.consume_spaces();
+
+        if self.consume_if(TokenKind::Semicolon).is_some() {
+            return Ok(());
+        }
+
+        match self.peek() {
+            None | Some(TokenKind::RBrace) => return Ok(()),
+            Some(_) if self.newline_before() => return Ok(()),
+            Some(kind) => return Err(format!("expected `;` but found {:?}", kind)),
+        }
+    }
+
+    // Ends type arguments, taking one `>` at a time out of `>>` and `>>>`,
+    // and out of `>=`, `>>=` and `>>>=`.
+    fn consume_type_args_end(&mut self) -> bool {
+        if self.split_gts > 0 {
+            self.split_gts -= 1;
+            return true;
+        }
+
+        if self.split_eq {
+            return false;
+        }
+
+        let (split_gts, split_eq) = match self.peek() {
+            Some(TokenKind::Gt) => (0, false),
+            Some(TokenKind::Shr) => (1, false),
+            Some(TokenKind::UShr) => (2, false),
+            Some(TokenKind::Geq) => (0, true),
+            Some(TokenKind::ShrEq) => (1, true),
+            Some(TokenKind::UShrEq) => (2, true),
+            _ => return false,
+        };
+
+        self.incr();
+        self.split_gts = split_gts;
+        self.split_eq = split_eq;
+
+        return true;
+    }
+
+    // Whether the type being parsed has to end here, because the rest of a
+    // split token is next.
+    fn in_split_token(&self) -> bool {
+        return self.split_gts > 0 || self.split_eq;
+    }
+
+    // An `=`, which might be the one left of a `>=` that closed type
+    // arguments.
+    fn consume_eq(&mut self) -> bool {
+        if self.split_eq {
+            if self.split_gts > 0 {
+                return false;
+            }
+
+            self.split_eq = false;
+            return true;
+        }
+
+        return self.consume_if(TokenKind::Eq).is_some();
+    }
+
+    // Types can end in the middle of a `>>`, but only if it's closing more
+    // type arguments, and in the middle of a `>=` only where `consume_eq`
+    // takes the `=`.
+    fn check_type_end(&self) -> Result<(), String> {
+        if self.split_gts > 0 {
+            return Err(format!("unexpected `>` after a type"));
+        }
+
+        if self.split_eq {
+            return Err(format!("unexpected `=` after a type"));
+        }
+
+        return Ok(());
+    }
+
+    // The kind of the `n`th non-trivia token from here, where `peek_nth(0)`
+    // is the next one.
+    fn peek_nth(&self, n: usize) -> Option<TokenKind> {
+        let kinds = self.tokens.kind[self.index..].iter();
+        return kinds.filter(|kind| !kind.is_trivia()).nth(n).copied();
+    }
+
+    // At an `async`, whether it starts an async function, which needs the
+    // `function` to be on the same line.
+    fn async_function_ahead(&self) -> bool {
+        let mut indices = self.index + 1..self.tokens.len();
+        let Some(index) = indices.find(|&index| !self.tokens.kind[index].is_trivia()) else {
+            return false;
+        };
+
+        return self.tokens.kind[index] == TokenKind::Key(Key::Function)
+            && !self.tokens.newline_before[index];
+    }
+
+    // After the `)` of what might be an arrow function, whether the `:` next
+    // starts a return type, instead of being part of a conditional like
+    // `a ? (b) : c`. This looks for a `=>` after the type, and right in the
+    // true branch of a conditional, also for the conditional's `:` after the
+    // arrow function, so `a ? (b) : c => d` isn't one but
+    // `a ? (b): c => d : e` is.
+    fn arrow_after_return_type(&self) -> bool {
+        let mut depth = 0;
+        for index in self.index + 1..self.tokens.len() {
+            let kind = self.tokens.kind[index];
+            let closes = match kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace | TokenKind::Lt => {
+                    depth += 1;
+                    continue;
+                }
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace | TokenKind::Gt => 1,
+                TokenKind::Shr => 2,
+                TokenKind::UShr => 3,
+                _ => 0,
+            };
+
+            if closes > depth {
+                return false;
+            }
+            depth -= closes;
+
+            if depth > 0 {
+                continue;
+            }
+
+            match kind {
+                TokenKind::Arrow if self.tokens.newline_before[index] => return false,
+                TokenKind::Arrow => {
+                    return !self.in_true_branch() || self.conditional_colon_after(index);
+                }
+                TokenKind::Semicolon
+                | TokenKind::Comma
+                | TokenKind::Eq
+                | TokenKind::Colon
+                | TokenKind::Question => return false,
+                _ => {}
+            }
+        }
+
+        return false;
+    }
+
+    // Whether the next token is right in the true branch of the innermost
+    // conditional, and not inside brackets in it.
+    fn in_true_branch(&self) -> bool {
+        let Some(&start) = self.conditionals.last() else {
+            return false;
+        };
+
+        let mut depth = 0;
+        for kind in &self.tokens.kind[start..self.index] {
+            match kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+        }
+
+        return depth == 0;
+    }
+
+    // Whether the arrow function body after the `=>` at `index` is followed
+    // by the `:` of the conditional it's in, skipping over any conditionals
+    // in the body itself.
+    fn conditional_colon_after(&self, index: usize) -> bool {
+        let mut depth = 0;
+        let mut questions = 0;
+        for &kind in &self.tokens.kind[index + 1..] {
+            match kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace if depth == 0 => {
+                    return false;
+                }
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth -= 1,
+                _ if depth > 0 => {}
+                TokenKind::Question => questions += 1,
+                TokenKind::Colon if questions == 0 => return true,
+                TokenKind::Colon => questions -= 1,
+                TokenKind::Semicolon | TokenKind::Comma => return false,
+                _ => {}
+            }
+        }
+
+        return false;
+    }
+
+    // Inside the parentheses of what might be an arrow function's parameters,
+    // whether there's a `=>` or a return type after the `)`.
+    fn params_ahead(&self) -> bool {
+        let mut depth = 0;
+        for index in self.index..self.tokens.len() {
+            match self.tokens.kind[index] {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen if depth == 0 => {
+                    let kinds = self.tokens.kind[index + 1..].iter();
+                    let mut kinds = kinds.filter(|kind| !kind.is_trivia());
+                    return matches!(kinds.next(), Some(TokenKind::Arrow | TokenKind::Colon));
+                }
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+        }
+
+        return false;
+    }
+
+    // At the `<` of what might be a generic arrow function, whether there's
+    // a `(` after the matching `>`. Otherwise it's a type assertion, like
+    // `<T>a`.
+    fn generic_arrow_ahead(&self) -> bool {
+        let mut depth = 0;
+        for index in self.index..self.tokens.len() {
+            let closes = match self.tokens.kind[index] {
+                TokenKind::Lt => {
+                    depth += 1;
+                    continue;
+                }
+                TokenKind::Gt => 1,
+                TokenKind::Shr => 2,
+                TokenKind::UShr => 3,
+                TokenKind::Semicolon | TokenKind::LBrace | TokenKind::RBrace => return false,
+                _ => continue,
+            };
+
+            if closes > depth {
+                return false;
+            }
+            depth -= closes;
+
+            if depth == 0 {
+                let kinds = self.tokens.kind[index + 1..].iter();
+                let mut kinds = kinds.filter(|kind| !kind.is_trivia());
+                return kinds.next() == Some(&TokenKind::LParen);
+            }
+        }
+
+        return false;
+    }
+
     fn peek(&self) -> Option<TokenKind> {
         return self.tokens.get(self.index).map(|s| *s.kind);
     }
 
-    fn peek_ref(&self) -> Option<TokenRef> {
+    // Contextual keywords are words with a fixed symbol id, so this is just
+    // an integer compare.
+    fn peek_ctx_key(&self) -> Option<CtxKey> {
+        let token = self.tokens.get(self.index)?;
+        if *token.kind != TokenKind::Word {
+            return None;
+        }
+
+        return CtxKey::from_symbol(*token.extra);
+    }
+
+    fn consume_ctx_key(&mut self, key: CtxKey) -> Option<TokenRef<'_>> {
+        if self.peek_ctx_key() != Some(key) {
+            return None;
+        }
+
+        self.index += 1;
+
+        return self.tokens.get(self.index - 1);
+    }
+
+    fn peek_ref(&self) -> Option<TokenRef<'_>> {
         return self.tokens.get(self.index);
     }
 
@@ -99,12 +399,17 @@ impl<'a> ParseContext<'a> {
     }
 }
 
-pub fn parse(tokens: &TokenVec) -> Result<AstNodeVec, String> {
+pub fn parse(tokens: &TokenVec, symbols: &Symbols) -> Result<AstNodeVec, String> {
     let mut ctx = ParseContext {
         index: 0,
         tokens: tokens.as_slice(),
         parse_stack: Vec::with_capacity(32),
         tree: AstNodeVec::new(),
+        function_depth: 0,
+        split_gts: 0,
+        split_eq: false,
+        target_symbol: symbols.from_str("target"),
+        conditionals: Vec::new(),
     };
 
     ctx.tree.push(AstNode {
@@ -115,16 +420,10 @@ pub fn parse(tokens: &TokenVec) -> Result<AstNodeVec, String> {
 
     ctx.parse_stack.push(StackEntry {
         proc: parse_stmt,
-        state: Some(StackState {
-            start_token: 0,
-            start_tree_index: 1,
-        }),
+        state: Some(ctx.here()),
     });
 
-    let state = StackState {
-        start_token: 0,
-        start_tree_index: 1,
-    };
+    let state = ctx.here();
 
     fn parse_inf(ctx: &mut ParseContext, _: StackState) -> Result<(), String> {
         if ctx.peek().is_none() {
@@ -139,10 +438,7 @@ pub fn parse(tokens: &TokenVec) -> Result<AstNodeVec, String> {
     ctx.push_state(state, parse_inf);
 
     while let Some(StackEntry { proc, state }) = ctx.parse_stack.pop() {
-        let state = state.unwrap_or(StackState {
-            start_token: ctx.index as u32,
-            start_tree_index: ctx.tree.len() as u32,
-        });
+        let state = state.unwrap_or_else(|| ctx.here());
         proc(&mut ctx, state)?;
     }
 
@@ -212,33 +508,72 @@ fn parse_stmt(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
             return Ok(());
         }
 
+        TokenKind::Key(key @ (Key::Var | Key::Let | Key::Const)) => {
+            ctx.incr();
+
+            let op = match key {
+                Key::Var => AstNodeKind::StmtVar,
+                Key::Let => AstNodeKind::StmtLet,
+                _ => AstNodeKind::StmtConst,
+            };
+            let state = StackState {
+                op: Some(op),
+                ..state
+            };
+
+            ctx.push_state(state, |ctx, state| {
+                ctx.add_node(&state, state.op.unwrap());
+                return ctx.consume_semicolon();
+            });
+
+            return parse_declarator(ctx, state);
+        }
+
         TokenKind::LBrace => {
             ctx.incr();
             ctx.add_node(&state, AstNodeKind::StmtBlockIntro);
+            ctx.push_state(state, block_end);
 
-            const BLOCK_END: ParseStackFunc = |ctx, state| {
-                ctx.consume_spaces();
+            return Ok(());
+        }
 
-                let Some(_) = ctx.consume_if(TokenKind::RBrace) else {
-                    ctx.push_state(state, BLOCK_END);
-                    ctx.push_proc(parse_stmt);
-                    return Ok(());
-                };
+        TokenKind::Key(Key::Function) | TokenKind::Key(Key::Async)
+            if tok == TokenKind::Key(Key::Function) || ctx.async_function_ahead() =>
+        {
+            parse_function(ctx, state)?;
 
-                ctx.add_node(&state, AstNodeKind::StmtBlock);
-                return Ok(());
-            };
+            if ctx.tree.extra[state.start_tree_index as usize] == 0 {
+                return Err(format!("function declaration missing a name"));
+            }
+
+            return Ok(());
+        }
+
+        // `return\nx` returns nothing, then evaluates `x`.
+        TokenKind::Key(Key::Return) => {
+            if ctx.function_depth == 0 {
+                return Err(format!("return statement outside of a function"));
+            }
+
+            ctx.incr();
+            ctx.push_state(state, |ctx, state| {
+                ctx.add_node(&state, AstNodeKind::StmtReturn);
+                return ctx.consume_semicolon();
+            });
 
-            ctx.push_state(state, BLOCK_END);
+            ctx.consume_spaces();
+            match ctx.peek() {
+                None | Some(TokenKind::Semicolon | TokenKind::RBrace) => {}
+                Some(_) if ctx.newline_before() => {}
+                Some(_) => ctx.push_proc(parse_expr),
+            }
 
             return Ok(());
         }
 
         _ => {
             ctx.push_proc(|ctx, _state| {
-                ctx.consume_spaces();
-                ctx.consume_if(TokenKind::Semicolon);
-                return Ok(());
+                return ctx.consume_semicolon();
             });
 
             return parse_expr(ctx, state);
@@ -246,74 +581,2368 @@ fn parse_stmt(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
     }
 }
 
-fn parse_expr(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
-    let tok = match ctx.peek_ref() {
-        None => return Ok(()),
-        Some(t) => t,
+// The statements of a block, after the `{`.
+fn block_end(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    let Some(_) = ctx.consume_if(TokenKind::RBrace) else {
+        ctx.push_state(state, block_end);
+        ctx.push_proc(parse_stmt);
+        return Ok(());
     };
 
-    match tok.kind {
-        TokenKind::Number => {
-            ctx.incr();
-            ctx.add_node(&state, AstNodeKind::ExprNumber);
+    ctx.add_node(&state, AstNodeKind::StmtBlock);
+    return Ok(());
+}
+
+// A function, starting at the `function` keyword, or at the `async` before
+// it. The intro's extra is the name's symbol, or 0 if it doesn't have one, and
+// the end's extra is 1 for an `async` function.
+fn parse_function(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    let is_async = ctx.consume_if(TokenKind::Key(Key::Async)).is_some();
+    ctx.consume_spaces();
+    ctx.incr();
+    ctx.consume_spaces();
+
+    if ctx.peek() == Some(TokenKind::Mult) {
+        return Err(format!("generator functions aren't supported yet"));
+    }
+
+    let name = ctx.consume_if(TokenKind::Word).map_or(0, |tok| *tok.extra);
+    ctx.add_node_extra(&state, AstNodeKind::ExprFunctionIntro, name);
+    ctx.function_depth += 1;
+
+    fn function_end(ctx: &mut ParseContext, state: StackState, is_async: bool) {
+        ctx.function_depth -= 1;
+        ctx.add_node_extra(&state, AstNodeKind::ExprFunction, is_async as u32);
+    }
+
+    ctx.push_state(
+        state,
+        match is_async {
+            false => |ctx, state| {
+                function_end(ctx, state, false);
+                return Ok(());
+            },
+            true => |ctx, state| {
+                function_end(ctx, state, true);
+                return Ok(());
+            },
+        },
+    );
+
+    ctx.push_proc(|ctx, _state| {
+        ctx.consume_spaces();
+
+        let state = ctx.here();
+        let Some(_) = ctx.consume_if(TokenKind::LBrace) else {
+            return Err(format!("function missing a body"));
+        };
+
+        ctx.add_node(&state, AstNodeKind::StmtBlockIntro);
+        ctx.push_state(state, block_end);
+
+        return Ok(());
+    });
+
+    // The return type
+    ctx.push_proc(|ctx, _state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::Colon).is_none() {
+            return Ok(());
         }
 
-        TokenKind::Key(Key::True) => {
-            ctx.incr();
-            ctx.add_node(&state, AstNodeKind::ExprBoolean);
+        ctx.push_proc(|ctx, _state| return ctx.check_type_end());
+        ctx.push_proc(parse_type);
+
+        return Ok(());
+    });
+
+    ctx.push_proc(parse_params);
+
+    return Ok(());
+}
+
+// A parenthesized parameter list.
+fn parse_params(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    // After a parameter; `state` is the list's.
+    const PARAMS_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::RParen).is_some() {
+            ctx.add_node(&state, AstNodeKind::ExprParams);
+            return Ok(());
         }
 
-        TokenKind::Word => {
-            let extra = *tok.extra;
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Err(format!("parameters missing closing parenthesis"));
+        };
+
+        return PARAM(ctx, state);
+    };
+
+    const PARAM: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::RParen).is_some() {
+            ctx.add_node(&state, AstNodeKind::ExprParams);
+            return Ok(());
+        }
+
+        ctx.push_state(state, PARAMS_REST);
+
+        let param = ctx.here();
+        ctx.push_state(param, param_end);
+
+        // `this: T` declares the type of `this`, and has to come first.
+        if ctx.peek() == Some(TokenKind::Key(Key::This)) {
+            if ctx.tree.kind[ctx.tree.len() - 1] != AstNodeKind::ExprParamsIntro {
+                return Err(format!("`this` parameter must be the first parameter"));
+            }
+
             ctx.incr();
-            ctx.add_node_extra(&state, AstNodeKind::ExprWord, extra);
+            ctx.add_node(&param, AstNodeKind::PatThis);
+
+            return Ok(());
         }
 
-        _ => {
-            unimplemented!();
+        if ctx.consume_if(TokenKind::Spread).is_some() {
+            let op = Some(AstNodeKind::PatRest);
+            ctx.push_state(StackState { op, ..param }, add_op);
         }
-    }
+
+        ctx.push_proc(parse_pattern);
+
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    let state = ctx.here();
+    let Some(_) = ctx.consume_if(TokenKind::LParen) else {
+        return Err(format!("function missing parameters"));
+    };
+
+    ctx.add_node(&state, AstNodeKind::ExprParamsIntro);
+    ctx.push_state(state, PARAM);
 
     return Ok(());
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::*;
-    use crate::util::*;
+// The rest of a parameter after its pattern: `?`, then a type, then a
+// default, though optional and rest parameters can't have defaults.
+fn param_end(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    const PARAM_DEFAULT: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
 
-    #[test_resources("test/easy/conditional.*")]
-    fn parse_easy(path: &str) {
-        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+        let has_default = ctx.consume_eq();
+        ctx.check_type_end()?;
 
-        let mut symbols = Symbols::new();
-        let tokens = lex(&source, &mut symbols)
-            .map_err(|e| e.error)
-            .expect("doesn't error");
+        if !has_default {
+            ctx.add_node(&state, AstNodeKind::PatParam);
+            return Ok(());
+        }
 
-        let ast = parse(&tokens).expect("doesn't error");
+        let pattern = first_child(&ctx.tree, state.start_tree_index as usize, ctx.tree.len());
+        if ctx.tree.kind[pattern] == AstNodeKind::PatRest {
+            return Err(format!("parameter can't have a default here"));
+        }
 
-        let mut output = Vec::new();
-        for token in &ast {
-            println!("{:?}", token.to_owned());
+        ctx.push_state(state, |ctx, state| {
+            ctx.add_node(&state, AstNodeKind::PatParam);
+            return Ok(());
+        });
+        ctx.push_proc(parse_assign);
 
-            output.push(format!("{:?}", token.kind));
+        return Ok(());
+    };
+
+    const OPTIONAL_PARAM_END: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_eq() {
+            return Err(format!("parameter can't have a default here"));
         }
 
-        let doc = match extract_yaml(&source) {
-            None => return,
-            Some(d) => d,
-        };
-        let expected_token_string = doc["ast"].as_str().unwrap_or("");
+        ctx.check_type_end()?;
+        ctx.add_node_extra(&state, AstNodeKind::PatParam, 1);
 
-        let mut expected_tokens = Vec::with_capacity(output.len());
-        expected_tokens.push(AstNodeKind::UtilSentinel.into());
-        for token in expected_token_string.trim().split(",") {
-            let token = token.trim();
-            expected_tokens.push(token);
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    let mut end = PARAM_DEFAULT;
+    if ctx.consume_if(TokenKind::Question).is_some() {
+        if ctx.tree.kind[ctx.tree.len() - 1] == AstNodeKind::PatRest {
+            return Err(format!("rest parameter can't be optional"));
         }
 
-        assert_eq!(&output, &expected_tokens);
+        end = OPTIONAL_PARAM_END;
+        ctx.consume_spaces();
+    }
+
+    if ctx.consume_if(TokenKind::Colon).is_some() {
+        ctx.push_state(state, end);
+        ctx.push_proc(parse_type);
+
+        return Ok(());
+    }
+
+    return end(ctx, state);
+}
+
+// An Expression, which is assignment expressions separated by commas.
+fn parse_expr(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    const COMMA_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Ok(());
+        };
+
+        ctx.push_state(state, |ctx, state| {
+            ctx.add_node(&state, AstNodeKind::ExprComma);
+            ctx.push_state(state, COMMA_REST);
+            return Ok(());
+        });
+        ctx.push_proc(parse_assign);
+
+        return Ok(());
+    };
+
+    ctx.push_state(ctx.here(), COMMA_REST);
+    ctx.push_proc(parse_assign);
+
+    return Ok(());
+}
+
+// An AssignmentExpression, i.e. any expression without a top-level comma.
+// Conditionals and assignments are right-associative, so their right sides
+// just go back through here.
+fn parse_assign(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    let state = ctx.here();
+
+    ctx.push_state(state, |ctx, state| {
+        ctx.consume_spaces();
+
+        let Some(kind) = ctx.peek() else {
+            return Ok(());
+        };
+
+        // An arrow function with a block body, like `() => {}`, can't be
+        // the start of a bigger expression.
+        if ctx.tree.kind[ctx.tree.len() - 1] == AstNodeKind::ExprArrow {
+            return Ok(());
+        }
+
+        if kind == TokenKind::Question {
+            ctx.incr();
+            ctx.conditionals.push(ctx.index);
+
+            ctx.push_state(state, |ctx, state| {
+                ctx.add_node(&state, AstNodeKind::ExprConditional);
+                return Ok(());
+            });
+            ctx.push_proc(parse_assign);
+            ctx.push_proc(|ctx, _state| {
+                ctx.consume_spaces();
+
+                let Some(_) = ctx.consume_if(TokenKind::Colon) else {
+                    return Err(format!("conditional expression missing `:`"));
+                };
+                ctx.conditionals.pop();
+
+                return Ok(());
+            });
+            ctx.push_proc(parse_assign);
+
+            return Ok(());
+        }
+
+        let Some(op) = assign_op(kind) else {
+            return Ok(());
+        };
+
+        if !is_assign_target(&ctx.tree) {
+            return Err(format!("invalid left-hand side of {:?}", kind));
+        }
+
+        ctx.incr();
+        ctx.push_state(
+            StackState {
+                op: Some(op),
+                ..state
+            },
+            add_op,
+        );
+        ctx.push_proc(parse_assign);
+
+        return Ok(());
+    });
+
+    ctx.push_state(
+        StackState {
+            min_prec: 1,
+            ..state
+        },
+        parse_binary,
+    );
+
+    return Ok(());
+}
+
+// Precedence climbing: parses a unary expression, then keeps taking binary
+// operators that bind at least as tightly as `state.min_prec`, parsing each
+// right side with a higher minimum.
+fn parse_binary(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    const BINARY_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        let Some((op, prec)) = ctx.peek().and_then(binary_op) else {
+            return Ok(());
+        };
+
+        if prec < state.min_prec || ctx.tree.kind[ctx.tree.len() - 1] == AstNodeKind::ExprArrow {
+            return Ok(());
+        }
+
+        ctx.incr();
+
+        ctx.push_state(
+            StackState {
+                op: Some(op),
+                ..state
+            },
+            |ctx, state| {
+                let op = state.op.unwrap();
+                check_operands(&ctx.tree, op)?;
+                ctx.add_node(&state, op);
+
+                ctx.push_state(StackState { op: None, ..state }, BINARY_REST);
+                return Ok(());
+            },
+        );
+
+        // `**` is right-associative, so its right side can have more of it.
+        let min_prec = match op {
+            AstNodeKind::ExprExp => prec,
+            _ => prec + 1,
+        };
+        ctx.consume_spaces();
+        ctx.push_state(
+            StackState {
+                min_prec,
+                ..ctx.here()
+            },
+            parse_binary,
+        );
+
+        return Ok(());
+    };
+
+    ctx.push_state(state, BINARY_REST);
+    ctx.push_state(state, parse_unary);
+
+    return Ok(());
+}
+
+// `state.min_prec` is the precedence of the operator this is an operand of,
+// if any, which `parse_primary` needs to rule out arrow functions.
+fn parse_unary(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    let state = StackState {
+        min_prec: state.min_prec,
+        ..ctx.here()
+    };
+
+    if let Some(op) = ctx.peek().and_then(prefix_op) {
+        ctx.incr();
+
+        ctx.push_state(
+            StackState {
+                op: Some(op),
+                ..state
+            },
+            |ctx, state| {
+                let op = state.op.unwrap();
+                let is_update = matches!(op, AstNodeKind::ExprPreIncr | AstNodeKind::ExprPreDecr);
+                if is_update && !is_assign_target(&ctx.tree) {
+                    return Err(format!("invalid operand for prefix {:?}", op));
+                }
+
+                ctx.add_node(&state, op);
+                return Ok(());
+            },
+        );
+
+        // A prefix operator binds tighter than any binary one.
+        let operand = StackState {
+            min_prec: UNARY_PREC,
+            ..state
+        };
+        ctx.push_state(operand, parse_unary);
+
+        return Ok(());
+    }
+
+    // `a\n++b` is `a; ++b`, so postfix operators can't come after a newline.
+    ctx.push_state(state, |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.newline_before() {
+            return Ok(());
+        }
+
+        let op = match ctx.peek() {
+            Some(TokenKind::PlusPlus) => AstNodeKind::ExprPostIncr,
+            Some(TokenKind::MinusMinus) => AstNodeKind::ExprPostDecr,
+            _ => return Ok(()),
+        };
+
+        if !is_assign_target(&ctx.tree) {
+            return Err(format!("invalid operand for postfix {:?}", op));
+        }
+
+        ctx.incr();
+        ctx.add_node(&state, op);
+
+        return Ok(());
+    });
+
+    ctx.push_state(state, parse_member_chain);
+
+    return parse_primary(ctx, state);
+}
+
+// Member accesses, calls and tagged templates after a primary expression.
+// `state.op` is `Some(ExprNew)` in the callee of a `new`, which ends before
+// the arguments, and `Some(ExprOptChain)` once the chain has had a `?.`.
+fn parse_member_chain(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    // An arrow function's body already took everything it could.
+    if ctx.tree.kind[ctx.tree.len() - 1] == AstNodeKind::ExprArrow {
+        return Ok(());
+    }
+
+    let in_new = state.op == Some(AstNodeKind::ExprNew);
+
+    match ctx.peek() {
+        Some(TokenKind::Dot) => {
+            ctx.incr();
+            parse_member_name(ctx, &state)?;
+            ctx.push_state(state, parse_member_chain);
+        }
+
+        Some(TokenKind::OptChain) => {
+            if in_new {
+                return Err(format!("optional chain in the callee of `new`"));
+            }
+
+            ctx.incr();
+            ctx.add_node(&state, AstNodeKind::ExprOptional);
+
+            let state = StackState {
+                op: Some(AstNodeKind::ExprOptChain),
+                ..state
+            };
+
+            ctx.consume_spaces();
+            match ctx.peek() {
+                Some(TokenKind::LBracket) => {
+                    ctx.incr();
+                    ctx.push_state(state, index_end);
+                    ctx.push_proc(parse_expr);
+                }
+
+                Some(TokenKind::LParen) => {
+                    ctx.incr();
+                    ctx.push_state(state, call_end);
+                    ctx.push_proc(parse_args);
+                }
+
+                _ => {
+                    parse_member_name(ctx, &state)?;
+                    ctx.push_state(state, parse_member_chain);
+                }
+            }
+        }
+
+        Some(TokenKind::LBracket) => {
+            ctx.incr();
+            ctx.push_state(state, index_end);
+            ctx.push_proc(parse_expr);
+        }
+
+        Some(TokenKind::LParen) if !in_new => {
+            ctx.incr();
+            ctx.push_state(state, call_end);
+            ctx.push_proc(parse_args);
+        }
+
+        Some(TokenKind::StrTemplate | TokenKind::StrTemplateBegin) => {
+            if state.op == Some(AstNodeKind::ExprOptChain) {
+                return Err(format!("tagged template in an optional chain"));
+            }
+
+            ctx.push_state(state, |ctx, state| {
+                ctx.add_node(&state, AstNodeKind::ExprTaggedTemplate);
+                ctx.push_state(state, parse_member_chain);
+                return Ok(());
+            });
+            ctx.push_proc(parse_template);
+        }
+
+        _ => {
+            if state.op == Some(AstNodeKind::ExprOptChain) {
+                ctx.add_node(&state, AstNodeKind::ExprOptChain);
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn index_end(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    let Some(_) = ctx.consume_if(TokenKind::RBracket) else {
+        return Err(format!("computed member access missing closing bracket"));
+    };
+
+    ctx.add_node(&state, AstNodeKind::ExprIndex);
+    ctx.push_state(state, parse_member_chain);
+
+    return Ok(());
+}
+
+fn call_end(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.add_node(&state, AstNodeKind::ExprCall);
+    ctx.push_state(state, parse_member_chain);
+
+    return Ok(());
+}
+
+// The name after a `.` or `?.`, which can be a keyword, as in `a.if`.
+fn parse_member_name(ctx: &mut ParseContext, state: &StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    let (kind, extra) = match ctx.peek_ref() {
+        Some(tok) => (*tok.kind, *tok.extra),
+        None => {
+            return Err(format!(
+                "expected a property name but found the end of the file"
+            ))
+        }
+    };
+
+    let (node, extra) = match kind {
+        TokenKind::Word => (AstNodeKind::ExprMember, extra),
+        TokenKind::Key(key) => (AstNodeKind::ExprMember, key.symbol()),
+        TokenKind::PrivateName => (AstNodeKind::ExprPrivateMember, extra),
+        _ => return Err(format!("expected a property name but found {:?}", kind)),
+    };
+
+    ctx.incr();
+    ctx.add_node_extra(state, node, extra);
+
+    return Ok(());
+}
+
+// Arguments of a call or `new`, after the opening parenthesis. Each one can
+// be spread, and there can be a trailing comma.
+fn parse_args(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    const ARGS_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::Comma).is_none() {
+            let Some(_) = ctx.consume_if(TokenKind::RParen) else {
+                return Err(format!("arguments missing closing parenthesis"));
+            };
+
+            return Ok(());
+        }
+
+        return parse_args(ctx, state);
+    };
+
+    ctx.consume_spaces();
+
+    if ctx.consume_if(TokenKind::RParen).is_some() {
+        return Ok(());
+    }
+
+    ctx.push_proc(ARGS_REST);
+
+    let state = ctx.here();
+    if ctx.consume_if(TokenKind::Spread).is_some() {
+        let op = Some(AstNodeKind::ExprSpread);
+        ctx.push_state(StackState { op, ..state }, add_op);
+    }
+
+    ctx.push_proc(parse_assign);
+
+    return Ok(());
+}
+
+// A template literal, starting at its first piece.
+fn parse_template(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    const TEMPLATE_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        let (kind, extra) = match ctx.peek_ref() {
+            Some(tok) => (*tok.kind, *tok.extra),
+            None => return Err(format!("unterminated template literal")),
+        };
+
+        ctx.incr();
+        ctx.add_node_extra(&ctx.here(), AstNodeKind::ExprTemplatePiece, extra);
+
+        match kind {
+            TokenKind::StrTemplateMid => {
+                ctx.push_state(state, TEMPLATE_REST);
+                ctx.push_proc(parse_expr);
+            }
+            TokenKind::StrTemplateEnd => ctx.add_node(&state, AstNodeKind::ExprTemplate),
+            _ => {
+                return Err(format!(
+                    "expected the rest of a template but found {:?}",
+                    kind
+                ))
+            }
+        }
+
+        return Ok(());
+    };
+
+    let (kind, extra) = match ctx.peek_ref() {
+        Some(tok) => (*tok.kind, *tok.extra),
+        None => return Err(format!("expected a template but found the end of the file")),
+    };
+
+    ctx.incr();
+    ctx.add_node_extra(&state, AstNodeKind::ExprTemplateIntro, extra);
+
+    match kind {
+        TokenKind::StrTemplate => ctx.add_node(&state, AstNodeKind::ExprTemplate),
+        TokenKind::StrTemplateBegin => {
+            ctx.push_state(state, TEMPLATE_REST);
+            ctx.push_proc(parse_expr);
+        }
+        _ => return Err(format!("expected a template but found {:?}", kind)),
+    }
+
+    return Ok(());
+}
+
+// Arrow functions are only parsed when `state.min_prec` says this isn't the
+// operand of an operator, since they're a whole AssignmentExpression.
+fn parse_primary(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    let check_arrow = |state: &StackState| {
+        if state.min_prec > 1 {
+            return Err(format!("arrow function must be parenthesized here"));
+        }
+
+        return Ok(());
+    };
+
+    let tok = match ctx.peek_ref() {
+        None => {
+            return Err(format!(
+                "expected an expression but found the end of the file"
+            ))
+        }
+        Some(t) => t,
+    };
+
+    match tok.kind {
+        // The value is in `Symbols::numbers`.
+        TokenKind::Number
+        | TokenKind::OctNumber
+        | TokenKind::HexNumber
+        | TokenKind::BinNumber
+        | TokenKind::BigInt => {
+            let extra = *tok.extra;
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::ExprNumber, extra);
+        }
+
+        // The value is in `Symbols::strings`.
+        TokenKind::String => {
+            let extra = *tok.extra;
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::ExprString, extra);
+        }
+
+        // The extra is 1 for `true` and 0 for `false`.
+        TokenKind::Key(key @ (Key::True | Key::False)) => {
+            let value = *key == Key::True;
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::ExprBoolean, value as u32);
+        }
+
+        TokenKind::Key(Key::Null) => {
+            ctx.incr();
+            ctx.add_node(&state, AstNodeKind::ExprNull);
+        }
+
+        TokenKind::Key(Key::This) => {
+            ctx.incr();
+            ctx.add_node(&state, AstNodeKind::ExprThis);
+        }
+
+        // `super` can't be used on its own, only as `super.a`, `super[a]` or
+        // `super(a)`.
+        TokenKind::Key(Key::Super) => {
+            ctx.incr();
+
+            let Some(TokenKind::Dot | TokenKind::LBracket | TokenKind::LParen) = ctx.peek_nth(0)
+            else {
+                return Err(format!("expected `.`, `[` or `(` after `super`"));
+            };
+
+            ctx.add_node(&state, AstNodeKind::ExprSuper);
+        }
+
+        // `a => b` has a single parameter without parentheses.
+        TokenKind::Word => {
+            let extra = *tok.extra;
+            ctx.incr();
+            ctx.consume_spaces();
+
+            if ctx.peek() != Some(TokenKind::Arrow) || ctx.newline_before() {
+                ctx.add_node_extra(&state, AstNodeKind::ExprWord, extra);
+                return Ok(());
+            }
+
+            check_arrow(&state)?;
+            ctx.add_node(&state, AstNodeKind::ExprArrowIntro);
+            let params = ctx.here();
+            ctx.add_node(&params, AstNodeKind::ExprParamsIntro);
+            let param = ctx.here();
+            ctx.add_node_extra(&param, AstNodeKind::PatWord, extra);
+            ctx.add_node(&param, AstNodeKind::PatParam);
+            ctx.add_node(&params, AstNodeKind::ExprParams);
+
+            return arrow_body(ctx, state);
+        }
+
+        // Without `async` as an identifier, `async (` or `async a` can only
+        // start an arrow function.
+        TokenKind::Key(Key::Async) => {
+            if ctx.async_function_ahead() {
+                return parse_function(ctx, state);
+            }
+
+            check_arrow(&state)?;
+            ctx.incr();
+            ctx.consume_spaces();
+
+            if ctx.newline_before() {
+                return Err(format!("line break after `async`"));
+            }
+
+            ctx.add_node_extra(&state, AstNodeKind::ExprArrowIntro, 1);
+
+            match ctx.peek_ref() {
+                Some(tok) if *tok.kind == TokenKind::Word => {
+                    let extra = *tok.extra;
+                    ctx.incr();
+
+                    let params = ctx.here();
+                    ctx.add_node(&params, AstNodeKind::ExprParamsIntro);
+                    let param = ctx.here();
+                    ctx.add_node_extra(&param, AstNodeKind::PatWord, extra);
+                    ctx.add_node(&param, AstNodeKind::PatParam);
+                    ctx.add_node(&params, AstNodeKind::ExprParams);
+
+                    return arrow_body(ctx, state);
+                }
+
+                Some(_) => {
+                    ctx.push_state(state, arrow_return_type);
+                    ctx.push_proc(parse_params);
+                }
+
+                None => return Err(format!("expected an arrow function after `async`")),
+            }
+        }
+
+        // `<T>(a: T) => a`, or a type assertion like `<T>a`, which isn't
+        // supported yet. In a `.tsx` file, the lexer already took JSX.
+        TokenKind::Lt => {
+            if !ctx.generic_arrow_ahead() {
+                return Err(format!("type assertions aren't supported yet"));
+            }
+
+            check_arrow(&state)?;
+            ctx.incr();
+            ctx.add_node(&state, AstNodeKind::ExprArrowIntro);
+
+            ctx.push_state(state, arrow_return_type);
+            ctx.push_proc(parse_params);
+            ctx.push_proc(|ctx, _state| return ctx.check_type_end());
+            ctx.push_state(ctx.here(), type_params_rest);
+        }
+
+        TokenKind::StrTemplate | TokenKind::StrTemplateBegin => {
+            return parse_template(ctx, state);
+        }
+
+        TokenKind::Key(Key::Function) => {
+            return parse_function(ctx, state);
+        }
+
+        // `new a.b(c)` calls `a.b`, so the callee is a member chain that
+        // stops at the arguments, which are optional.
+        TokenKind::Key(Key::New) => {
+            ctx.incr();
+            ctx.consume_spaces();
+
+            if ctx.consume_if(TokenKind::Dot).is_some() {
+                ctx.consume_spaces();
+
+                let is_target = match ctx.peek_ref() {
+                    Some(tok) => {
+                        *tok.kind == TokenKind::Word && Some(*tok.extra) == ctx.target_symbol
+                    }
+                    None => false,
+                };
+                if !is_target {
+                    return Err(format!("expected `new.target`"));
+                }
+                ctx.incr();
+
+                ctx.add_node(&state, AstNodeKind::ExprNewTarget);
+                return Ok(());
+            }
+
+            ctx.push_state(state, |ctx, state| {
+                ctx.consume_spaces();
+
+                if ctx.consume_if(TokenKind::LParen).is_none() {
+                    ctx.add_node(&state, AstNodeKind::ExprNew);
+                    return Ok(());
+                }
+
+                let op = Some(AstNodeKind::ExprNew);
+                ctx.push_state(StackState { op, ..state }, add_op);
+                ctx.push_proc(parse_args);
+
+                return Ok(());
+            });
+
+            let op = Some(AstNodeKind::ExprNew);
+            ctx.push_state(StackState { op, ..ctx.here() }, parse_member_chain);
+
+            let callee = StackState {
+                min_prec: UNARY_PREC,
+                ..ctx.here()
+            };
+            ctx.push_state(callee, parse_primary);
+        }
+
+        // This is either a parenthesized expression or an arrow function's
+        // parameters, which we can't tell until after the `)`; see
+        // `cover_elem`.
+        TokenKind::LParen => {
+            ctx.incr();
+            ctx.add_node(&state, AstNodeKind::ExprParenIntro);
+            ctx.push_state(state, cover_elem);
+        }
+
+        &kind => {
+            return Err(format!("expected an expression but found {:?}", kind));
+        }
+    }
+
+    return Ok(());
+}
+
+// One element of a parenthesized expression, which might turn out to be an
+// arrow function's parameter. Elements that can only be parameters, like
+// `...a` or `a: T`, are parsed as `PatParam`s and set `state.op` to
+// `ExprParams`, so that a missing `=>` is an error. Everything else is parsed
+// as an expression, and `cover_to_params` rewrites it if there's a `=>`,
+// though only names and names with defaults can be rewritten so far.
+fn cover_elem(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    // After an element; `state` is the parentheses'.
+    const COVER_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::RParen).is_some() {
+            return paren_end(ctx, state);
+        }
+
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Err(format!(
+                "parenthesized expression missing closing parenthesis"
+            ));
+        };
+
+        let last = ctx.tree.len() - 1;
+        if ctx.tree.kind[last] == AstNodeKind::PatParam {
+            let start = last + 1 - ctx.tree.subtree_size[last] as usize;
+            if ctx.tree.kind[first_child(&ctx.tree, start, last)] == AstNodeKind::PatRest {
+                return Err(format!("rest parameter must be the last parameter"));
+            }
+        }
+
+        return cover_elem(ctx, state);
+    };
+
+    ctx.consume_spaces();
+
+    let params = StackState {
+        op: Some(AstNodeKind::ExprParams),
+        ..state
+    };
+
+    // `()` and `(a,)` can only be parameters.
+    if ctx.consume_if(TokenKind::RParen).is_some() {
+        return paren_end(ctx, params);
+    }
+
+    let first = ctx.tree.kind[ctx.tree.len() - 1] == AstNodeKind::ExprParenIntro;
+    let elem = ctx.here();
+
+    // TODO: `([a] = b)` and `({ a: 1 })` are expressions too, but there are
+    // no array or object literals to parse them as yet, so for now anything
+    // starting with `[` or `{` has to be a parameter.
+    if let Some(TokenKind::LBracket | TokenKind::LBrace) = ctx.peek() {
+        if !ctx.params_ahead() {
+            return Err(format!("array and object literals aren't supported yet"));
+        }
+    }
+
+    let param_only = match (ctx.peek(), ctx.peek_nth(1), ctx.peek_nth(2)) {
+        (Some(TokenKind::Spread | TokenKind::LBracket | TokenKind::LBrace), _, _) => true,
+        (Some(TokenKind::Word), Some(TokenKind::Colon), _) => true,
+        (Some(TokenKind::Word), Some(TokenKind::Question), Some(next)) => matches!(
+            next,
+            TokenKind::Colon | TokenKind::Comma | TokenKind::RParen | TokenKind::Eq
+        ),
+        _ => false,
+    };
+
+    match param_only {
+        true => ctx.push_state(params, COVER_REST),
+        false => ctx.push_state(state, COVER_REST),
+    }
+
+    // Elements are joined with `ExprComma`s, same as in `parse_expr`.
+    if !first {
+        let list = StackState {
+            start_tree_index: state.start_tree_index + 1,
+            ..elem
+        };
+        let op = Some(AstNodeKind::ExprComma);
+        ctx.push_state(StackState { op, ..list }, add_op);
+    }
+
+    if !param_only {
+        ctx.push_proc(parse_assign);
+        return Ok(());
+    }
+
+    ctx.push_state(elem, param_end);
+
+    if ctx.consume_if(TokenKind::Spread).is_some() {
+        let op = Some(AstNodeKind::PatRest);
+        ctx.push_state(StackState { op, ..elem }, add_op);
+    }
+
+    ctx.push_proc(parse_pattern);
+
+    return Ok(());
+}
+
+// After the `)` of a parenthesized expression, which is an arrow function if
+// there's a `=>` next, possibly after a return type.
+fn paren_end(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    let is_arrow = match ctx.peek() {
+        Some(TokenKind::Arrow) => !ctx.newline_before(),
+        Some(TokenKind::Colon) => ctx.arrow_after_return_type(),
+        _ => false,
+    };
+
+    if !is_arrow {
+        if state.op == Some(AstNodeKind::ExprParams) {
+            return Err(format!("arrow function parameters missing `=>`"));
+        }
+
+        ctx.add_node(&state, AstNodeKind::ExprParen);
+        return Ok(());
+    }
+
+    // The `(` was parsed by `parse_primary`, which is where the state's from.
+    if state.min_prec > 1 {
+        return Err(format!("arrow function must be parenthesized here"));
+    }
+
+    cover_to_params(&mut ctx.tree, state.start_tree_index as usize)?;
+
+    return arrow_return_type(ctx, state);
+}
+
+// `(a, b = 1)` was parsed as a parenthesized expression, and now that there's
+// a `=>` after it, its elements need to become parameters. `start` is the
+// index of the `ExprParenIntro`, and everything after it is the elements.
+fn cover_to_params(tree: &mut AstNodeVec, start: usize) -> Result<(), String> {
+    use AstNodeKind::*;
+
+    let cover = tree.split_off(start);
+
+    // The elements are nested to the left in `ExprComma`s, so this finds
+    // them last to first.
+    let mut elems = Vec::new();
+    if cover.len() > 1 {
+        let mut elem = cover.len() - 1;
+        while cover.kind[elem] == ExprComma {
+            let right = elem - 1;
+            elems.push(right);
+            elem = right - cover.subtree_size[right] as usize;
+        }
+
+        elems.push(elem);
+    }
+
+    let node = |kind, subtree_size, extra| AstNode {
+        kind,
+        subtree_size,
+        extra,
+    };
+    let copy = |tree: &mut AstNodeVec, range: std::ops::RangeInclusive<usize>| {
+        for index in range {
+            tree.push(cover.get(index).unwrap().to_owned());
+        }
+    };
+
+    tree.push(node(ExprArrowIntro, 1, 0));
+
+    let params = tree.len();
+    tree.push(node(ExprParamsIntro, 1, 0));
+
+    for &elem in elems.iter().rev() {
+        let param = tree.len();
+        let elem_start = elem + 1 - cover.subtree_size[elem] as usize;
+
+        match cover.kind[elem] {
+            PatParam => {
+                copy(tree, elem_start..=elem);
+                continue;
+            }
+
+            ExprWord => tree.push(node(PatWord, 1, cover.extra[elem])),
+
+            // `(a = 1) => a` has a default, but `(a.b = 1) => a` isn't a
+            // parameter.
+            ExprAssign
+                if cover.kind[elem_start] == ExprWord
+                    && first_child(&cover, elem_start, elem) == elem_start =>
+            {
+                tree.push(node(PatWord, 1, cover.extra[elem_start]));
+                copy(tree, elem_start + 1..=elem - 1);
+            }
+
+            kind => return Err(format!("invalid arrow function parameter {:?}", kind)),
+        }
+
+        let subtree_size = (tree.len() + 1 - param) as u32;
+        tree.push(node(PatParam, subtree_size, 0));
+    }
+
+    let subtree_size = (tree.len() + 1 - params) as u32;
+    tree.push(node(ExprParams, subtree_size, 0));
+
+    return Ok(());
+}
+
+// An arrow function's optional return type, then its `=>`.
+fn arrow_return_type(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    if ctx.consume_if(TokenKind::Colon).is_none() {
+        return arrow_body(ctx, state);
+    }
+
+    ctx.push_state(state, arrow_body);
+    ctx.push_proc(|ctx, _state| return ctx.check_type_end());
+    ctx.push_proc(parse_type);
+
+    return Ok(());
+}
+
+// The `=>` and body of an arrow function whose intro is at `state`. The body
+// is either a block or a single expression.
+fn arrow_body(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    if ctx.newline_before() || ctx.consume_if(TokenKind::Arrow).is_none() {
+        return Err(format!("arrow function missing `=>`"));
+    }
+
+    ctx.function_depth += 1;
+    ctx.push_state(state, |ctx, state| {
+        ctx.function_depth -= 1;
+        ctx.add_node(&state, AstNodeKind::ExprArrow);
+        return Ok(());
+    });
+
+    ctx.consume_spaces();
+
+    let body = ctx.here();
+    if ctx.consume_if(TokenKind::LBrace).is_some() {
+        ctx.add_node(&body, AstNodeKind::StmtBlockIntro);
+        ctx.push_state(body, block_end);
+    } else {
+        ctx.push_proc(parse_assign);
+    }
+
+    return Ok(());
+}
+
+// Type parameters, after the `<`. `state.op` is `Some(TypeParams)` once
+// there's been one.
+fn type_params_rest(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    // Each parameter's symbol comes from the name at its `start_token`.
+    const TYPE_PARAM_END: ParseStackFunc = |ctx, state| {
+        let name = ctx.tokens.extra[state.start_token as usize];
+        ctx.add_node_extra(&state, AstNodeKind::TypeParam, name);
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    let list = StackState {
+        op: Some(AstNodeKind::TypeParams),
+        ..state
+    };
+
+    if state.op == Some(AstNodeKind::TypeParams) {
+        if ctx.consume_type_args_end() {
+            ctx.add_node(&list, AstNodeKind::TypeParams);
+            return Ok(());
+        }
+
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Err(format!("type parameters missing closing `>`"));
+        };
+
+        ctx.consume_spaces();
+
+        // `<T,>` is allowed; it's how `.tsx` files tell these apart from JSX.
+        if ctx.consume_type_args_end() {
+            ctx.add_node(&list, AstNodeKind::TypeParams);
+            return Ok(());
+        }
+    }
+
+    let param = ctx.here();
+    let Some(_) = ctx.consume_if(TokenKind::Word) else {
+        return Err(format!("expected a type parameter name"));
+    };
+
+    ctx.push_state(list, type_params_rest);
+    ctx.consume_spaces();
+
+    if ctx.consume_if(TokenKind::Key(Key::Extends)).is_some() {
+        ctx.push_state(param, TYPE_PARAM_END);
+        ctx.push_proc(parse_type);
+
+        return Ok(());
+    }
+
+    return TYPE_PARAM_END(ctx, param);
+}
+
+// One declarator in a `var`, `let` or `const` list, whose kind is in
+// `state.op`. The rest of the list is parsed after it.
+fn parse_declarator(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    const DECL_LIST_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::Comma).is_none() {
+            return Ok(());
+        }
+
+        return parse_declarator(ctx, state);
+    };
+
+    const DECL_INIT: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        let has_init = ctx.consume_eq();
+        ctx.check_type_end()?;
+
+        if has_init {
+            ctx.push_state(state, |ctx, state| {
+                ctx.add_node(&state, AstNodeKind::StmtVarDecl);
+                return Ok(());
+            });
+            ctx.push_proc(parse_assign);
+
+            return Ok(());
+        }
+
+        if state.op == Some(AstNodeKind::StmtConst) {
+            return Err(format!("const declaration missing an initializer"));
+        }
+
+        let pattern = first_child(&ctx.tree, state.start_tree_index as usize, ctx.tree.len());
+        if ctx.tree.kind[pattern] != AstNodeKind::PatWord {
+            return Err(format!("destructuring declaration missing an initializer"));
+        }
+
+        ctx.add_node(&state, AstNodeKind::StmtVarDecl);
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    let decl = StackState {
+        op: state.op,
+        ..ctx.here()
+    };
+
+    ctx.push_state(state, DECL_LIST_REST);
+
+    ctx.push_state(decl, |ctx, state| {
+        ctx.consume_spaces();
+
+        // `let a!: T;` says `a` is assigned somewhere the checker can't see,
+        // so it needs a type and can't have an initializer.
+        if ctx.consume_if(TokenKind::Not).is_some() {
+            let last = ctx.tree.kind[ctx.tree.len() - 1];
+            if last != AstNodeKind::PatWord || state.op == Some(AstNodeKind::StmtConst) {
+                return Err(format!("definite assignment assertion not allowed here"));
+            }
+
+            ctx.consume_spaces();
+
+            let Some(_) = ctx.consume_if(TokenKind::Colon) else {
+                return Err(format!("definite assignment assertion missing a type"));
+            };
+
+            ctx.push_state(state, |ctx, state| {
+                ctx.consume_spaces();
+
+                ctx.check_type_end()?;
+
+                if ctx.peek() == Some(TokenKind::Eq) {
+                    return Err(format!("definite assignment assertion with an initializer"));
+                }
+
+                ctx.add_node_extra(&state, AstNodeKind::StmtVarDecl, 1);
+                return Ok(());
+            });
+            ctx.push_proc(parse_type);
+
+            return Ok(());
+        }
+
+        if ctx.consume_if(TokenKind::Colon).is_some() {
+            ctx.push_state(state, DECL_INIT);
+            ctx.push_proc(parse_type);
+
+            return Ok(());
+        }
+
+        return DECL_INIT(ctx, state);
+    });
+
+    ctx.push_proc(parse_pattern);
+
+    return Ok(());
+}
+
+// A binding pattern, without a default.
+fn parse_pattern(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    // After an array element; `state` is the array's.
+    const ARRAY_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::RBracket).is_some() {
+            ctx.add_node(&state, AstNodeKind::PatArray);
+            return Ok(());
+        }
+
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Err(format!("array pattern missing closing bracket"));
+        };
+
+        return ARRAY_ELEM(ctx, state);
+    };
+
+    const ARRAY_ELEM: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        let elem = ctx.here();
+        match ctx.peek() {
+            Some(TokenKind::RBracket) => {
+                ctx.incr();
+                ctx.add_node(&state, AstNodeKind::PatArray);
+            }
+
+            // `[a, , b]` skips an element.
+            Some(TokenKind::Comma) => {
+                ctx.incr();
+                ctx.add_node(&elem, AstNodeKind::PatHole);
+                ctx.push_state(state, ARRAY_ELEM);
+            }
+
+            // A rest element has to be last, without a trailing comma.
+            Some(TokenKind::Spread) => {
+                ctx.incr();
+                ctx.push_state(state, |ctx, state| {
+                    ctx.consume_spaces();
+
+                    let Some(_) = ctx.consume_if(TokenKind::RBracket) else {
+                        return Err(format!("rest element must be last in an array pattern"));
+                    };
+
+                    ctx.add_node(&state, AstNodeKind::PatArray);
+                    return Ok(());
+                });
+
+                let op = Some(AstNodeKind::PatRest);
+                ctx.push_state(StackState { op, ..elem }, add_op);
+                ctx.push_proc(parse_pattern);
+            }
+
+            _ => {
+                ctx.push_state(state, ARRAY_REST);
+                ctx.push_state(elem, pattern_default);
+                ctx.push_proc(parse_pattern);
+            }
+        }
+
+        return Ok(());
+    };
+
+    // After an object property; `state` is the object's.
+    const OBJECT_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_if(TokenKind::RBrace).is_some() {
+            ctx.add_node(&state, AstNodeKind::PatObject);
+            return Ok(());
+        }
+
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Err(format!("object pattern missing closing brace"));
+        };
+
+        return OBJECT_PROP(ctx, state);
+    };
+
+    const OBJECT_PROP: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        let prop = ctx.here();
+        let Some(tok) = ctx.peek_ref() else {
+            return Err(format!("object pattern missing closing brace"));
+        };
+        let (kind, extra) = (*tok.kind, *tok.extra);
+
+        // After the key, parses the `:` and the value, then adds the
+        // property.
+        let key_value = |ctx: &mut ParseContext| {
+            ctx.push_state(state, OBJECT_REST);
+            let op = Some(AstNodeKind::PatProp);
+            ctx.push_state(StackState { op, ..prop }, add_op);
+            ctx.push_proc(|ctx, _state| {
+                ctx.consume_spaces();
+
+                let Some(_) = ctx.consume_if(TokenKind::Colon) else {
+                    return Err(format!("object pattern property missing `:`"));
+                };
+
+                ctx.consume_spaces();
+                ctx.push_state(ctx.here(), pattern_default);
+                ctx.push_proc(parse_pattern);
+
+                return Ok(());
+            });
+        };
+
+        match kind {
+            TokenKind::RBrace => {
+                ctx.incr();
+                ctx.add_node(&state, AstNodeKind::PatObject);
+            }
+
+            // Only a plain name can come after `...` in an object pattern.
+            TokenKind::Spread => {
+                ctx.incr();
+                ctx.consume_spaces();
+
+                let Some(tok) = ctx.consume_if(TokenKind::Word) else {
+                    return Err(format!("object rest element must be a name"));
+                };
+                let extra = *tok.extra;
+
+                let name = ctx.here();
+                ctx.add_node_extra(&name, AstNodeKind::PatWord, extra);
+                ctx.add_node(&prop, AstNodeKind::PatRest);
+                ctx.consume_spaces();
+
+                let Some(_) = ctx.consume_if(TokenKind::RBrace) else {
+                    return Err(format!("rest element must be last in an object pattern"));
+                };
+
+                ctx.add_node(&state, AstNodeKind::PatObject);
+            }
+
+            TokenKind::LBracket => {
+                ctx.incr();
+                key_value(ctx);
+                ctx.push_proc(|ctx, _state| {
+                    ctx.consume_spaces();
+
+                    let Some(_) = ctx.consume_if(TokenKind::RBracket) else {
+                        return Err(format!("computed key missing closing bracket"));
+                    };
+
+                    return Ok(());
+                });
+                ctx.push_proc(parse_assign);
+            }
+
+            // `{ a }` and `{ a = 1 }` bind `a` directly.
+            TokenKind::Word => {
+                ctx.incr();
+                ctx.consume_spaces();
+
+                if ctx.peek() == Some(TokenKind::Colon) {
+                    ctx.add_node_extra(&prop, AstNodeKind::PatKey, extra);
+                    key_value(ctx);
+                    return Ok(());
+                }
+
+                ctx.add_node_extra(&prop, AstNodeKind::PatWord, extra);
+                ctx.push_state(state, OBJECT_REST);
+                return pattern_default(ctx, prop);
+            }
+
+            TokenKind::Key(key) => {
+                ctx.incr();
+                ctx.add_node_extra(&prop, AstNodeKind::PatKey, key.symbol());
+                key_value(ctx);
+            }
+
+            TokenKind::String => {
+                ctx.incr();
+                ctx.add_node_extra(&prop, AstNodeKind::PatStringKey, extra);
+                key_value(ctx);
+            }
+
+            TokenKind::Number
+            | TokenKind::OctNumber
+            | TokenKind::HexNumber
+            | TokenKind::BinNumber
+            | TokenKind::BigInt => {
+                ctx.incr();
+                ctx.add_node_extra(&prop, AstNodeKind::PatNumberKey, extra);
+                key_value(ctx);
+            }
+
+            _ => return Err(format!("expected a property name but found {:?}", kind)),
+        }
+
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    let state = ctx.here();
+    let Some(tok) = ctx.peek_ref() else {
+        return Err(format!(
+            "expected a binding pattern but found the end of the file"
+        ));
+    };
+
+    match *tok.kind {
+        TokenKind::Word => {
+            let extra = *tok.extra;
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::PatWord, extra);
+        }
+
+        TokenKind::LBracket => {
+            ctx.incr();
+            ctx.push_state(state, ARRAY_ELEM);
+        }
+
+        TokenKind::LBrace => {
+            ctx.incr();
+            ctx.push_state(state, OBJECT_PROP);
+        }
+
+        kind => return Err(format!("expected a binding pattern but found {:?}", kind)),
+    }
+
+    return Ok(());
+}
+
+// An optional `= value` after a pattern that started at `state`.
+fn pattern_default(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.consume_spaces();
+
+    if ctx.consume_if(TokenKind::Eq).is_none() {
+        return Ok(());
+    }
+
+    let op = Some(AstNodeKind::PatDefault);
+    ctx.push_state(StackState { op, ..state }, add_op);
+    ctx.push_proc(parse_assign);
+
+    return Ok(());
+}
+
+// The first of the children in `start..end`, i.e. the one whose subtree
+// starts at `start`.
+fn first_child(tree: &AstNodeVec, start: usize, end: usize) -> usize {
+    let mut index = end - 1;
+    while index + 1 - tree.subtree_size[index] as usize != start {
+        index -= tree.subtree_size[index] as usize;
+    }
+
+    return index;
+}
+
+// A type annotation: unions of intersections of array types of primary
+// types.
+fn parse_type(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    const UNION_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.in_split_token() || ctx.consume_if(TokenKind::BinOr).is_none() {
+            return Ok(());
+        }
+
+        ctx.push_state(state, |ctx, state| {
+            ctx.add_node(&state, AstNodeKind::TypeUnion);
+            ctx.push_state(state, UNION_REST);
+            return Ok(());
+        });
+        ctx.push_proc(parse_type_intersection);
+
+        return Ok(());
+    };
+
+    // A leading `|` is allowed, for lining up unions across lines.
+    ctx.consume_spaces();
+    ctx.consume_if(TokenKind::BinOr);
+    ctx.consume_spaces();
+
+    ctx.push_state(ctx.here(), UNION_REST);
+    ctx.push_proc(parse_type_intersection);
+
+    return Ok(());
+}
+
+fn parse_type_intersection(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    const INTERSECTION_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.in_split_token() || ctx.consume_if(TokenKind::BinAnd).is_none() {
+            return Ok(());
+        }
+
+        ctx.push_state(state, |ctx, state| {
+            ctx.add_node(&state, AstNodeKind::TypeIntersection);
+            ctx.push_state(state, INTERSECTION_REST);
+            return Ok(());
+        });
+        ctx.push_proc(parse_type_array);
+
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    ctx.push_state(ctx.here(), INTERSECTION_REST);
+    ctx.push_proc(parse_type_array);
+
+    return Ok(());
+}
+
+fn parse_type_array(ctx: &mut ParseContext, _state: StackState) -> Result<(), String> {
+    const ARRAY_SUFFIX: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.in_split_token() || ctx.newline_before() {
+            return Ok(());
+        }
+
+        if ctx.consume_if(TokenKind::LBracket).is_none() {
+            return Ok(());
+        }
+
+        ctx.consume_spaces();
+
+        let Some(_) = ctx.consume_if(TokenKind::RBracket) else {
+            return Err(format!("indexed access types aren't supported yet"));
+        };
+
+        ctx.add_node(&state, AstNodeKind::TypeArray);
+        ctx.push_state(state, ARRAY_SUFFIX);
+
+        return Ok(());
+    };
+
+    ctx.consume_spaces();
+
+    let state = ctx.here();
+    ctx.push_state(state, ARRAY_SUFFIX);
+
+    return parse_type_primary(ctx, state);
+}
+
+fn parse_type_primary(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    // Type arguments, after the `<`.
+    const TYPE_ARGS_REST: ParseStackFunc = |ctx, state| {
+        ctx.consume_spaces();
+
+        if ctx.consume_type_args_end() {
+            ctx.add_node(&state, AstNodeKind::TypeGeneric);
+            return Ok(());
+        }
+
+        let Some(_) = ctx.consume_if(TokenKind::Comma) else {
+            return Err(format!("type arguments missing closing `>`"));
+        };
+
+        ctx.push_state(state, TYPE_ARGS_REST);
+        ctx.push_proc(parse_type);
+
+        return Ok(());
+    };
+
+    let Some(tok) = ctx.peek_ref() else {
+        return Err(format!("expected a type but found the end of the file"));
+    };
+    let (kind, extra) = (*tok.kind, *tok.extra);
+
+    match kind {
+        TokenKind::Word => {
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::TypeRef, extra);
+            ctx.consume_spaces();
+
+            if ctx.newline_before() || ctx.consume_if(TokenKind::Lt).is_none() {
+                return Ok(());
+            }
+
+            ctx.push_state(state, TYPE_ARGS_REST);
+            ctx.push_proc(parse_type);
+        }
+
+        TokenKind::Key(key @ (Key::Void | Key::Null | Key::This | Key::True | Key::False)) => {
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::TypeKeyword, key.symbol());
+        }
+
+        TokenKind::String => {
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::TypeString, extra);
+        }
+
+        TokenKind::Number => {
+            ctx.incr();
+            ctx.add_node_extra(&state, AstNodeKind::TypeNumber, extra);
+        }
+
+        TokenKind::LParen => {
+            ctx.incr();
+            ctx.push_proc(|ctx, _state| {
+                ctx.consume_spaces();
+
+                let Some(_) = ctx.consume_if(TokenKind::RParen) else {
+                    return Err(format!("parenthesized type missing closing parenthesis"));
+                };
+
+                return Ok(());
+            });
+            ctx.push_proc(parse_type);
+        }
+
+        _ => return Err(format!("expected a type but found {:?}", kind)),
+    }
+
+    return Ok(());
+}
+
+fn add_op(ctx: &mut ParseContext, state: StackState) -> Result<(), String> {
+    ctx.add_node(&state, state.op.unwrap());
+    return Ok(());
+}
+
+// Binary operators and their precedence, where higher binds tighter. `??`
+// shares a level with `||`, but they can't be mixed; see `check_operands`.
+fn binary_op(kind: TokenKind) -> Option<(AstNodeKind, u8)> {
+    use AstNodeKind::*;
+
+    let op = match kind {
+        TokenKind::Nullish => (ExprNullish, 1),
+        TokenKind::BoolOr => (ExprBoolOr, 1),
+        TokenKind::BoolAnd => (ExprBoolAnd, 2),
+        TokenKind::BinOr => (ExprBinOr, 3),
+        TokenKind::BinXor => (ExprBinXor, 4),
+        TokenKind::BinAnd => (ExprBinAnd, 5),
+
+        TokenKind::EqEq => (ExprEqEq, 6),
+        TokenKind::Neq => (ExprNeq, 6),
+        TokenKind::EqEqEq => (ExprEqEqEq, 6),
+        TokenKind::NeqEq => (ExprNeqEq, 6),
+
+        TokenKind::Lt => (ExprLt, 7),
+        TokenKind::Gt => (ExprGt, 7),
+        TokenKind::Leq => (ExprLeq, 7),
+        TokenKind::Geq => (ExprGeq, 7),
+        TokenKind::Key(Key::Instanceof) => (ExprInstanceof, 7),
+        TokenKind::Key(Key::In) => (ExprIn, 7),
+
+        TokenKind::Shl => (ExprShl, 8),
+        TokenKind::Shr => (ExprShr, 8),
+        TokenKind::UShr => (ExprUShr, 8),
+
+        TokenKind::Add => (ExprAdd, 9),
+        TokenKind::Sub => (ExprSub, 9),
+
+        TokenKind::Mult => (ExprMult, 10),
+        TokenKind::Div => (ExprDiv, 10),
+        TokenKind::Mod => (ExprMod, 10),
+
+        TokenKind::Exp => (ExprExp, 11),
+
+        _ => return None,
+    };
+
+    return Some(op);
+}
+
+// Binds tighter than every binary operator. It's the `min_prec` of a prefix
+// operator's operand.
+const UNARY_PREC: u8 = 12;
+
+fn assign_op(kind: TokenKind) -> Option<AstNodeKind> {
+    use AstNodeKind::*;
+
+    let op = match kind {
+        TokenKind::Eq => ExprAssign,
+        TokenKind::AddEq => ExprAddEq,
+        TokenKind::SubEq => ExprSubEq,
+        TokenKind::MultEq => ExprMultEq,
+        TokenKind::DivEq => ExprDivEq,
+        TokenKind::ModEq => ExprModEq,
+        TokenKind::ExpEq => ExprExpEq,
+        TokenKind::ShlEq => ExprShlEq,
+        TokenKind::ShrEq => ExprShrEq,
+        TokenKind::UShrEq => ExprUShrEq,
+        TokenKind::BinAndEq => ExprBinAndEq,
+        TokenKind::BinOrEq => ExprBinOrEq,
+        TokenKind::BinXorEq => ExprBinXorEq,
+        TokenKind::BoolAndEq => ExprBoolAndEq,
+        TokenKind::BoolOrEq => ExprBoolOrEq,
+        TokenKind::NullishEq => ExprNullishEq,
+        _ => return None,
+    };
+
+    return Some(op);
+}
+
+fn prefix_op(kind: TokenKind) -> Option<AstNodeKind> {
+    use AstNodeKind::*;
+
+    let op = match kind {
+        TokenKind::Not => ExprNot,
+        TokenKind::BinNot => ExprBinNot,
+        TokenKind::Add => ExprPositive,
+        TokenKind::Sub => ExprNegative,
+        TokenKind::Key(Key::Typeof) => ExprTypeof,
+        TokenKind::Key(Key::Void) => ExprVoid,
+        TokenKind::Key(Key::Delete) => ExprDelete,
+        TokenKind::PlusPlus => ExprPreIncr,
+        TokenKind::MinusMinus => ExprPreDecr,
+        _ => return None,
+    };
+
+    return Some(op);
+}
+
+// Whether the expression that was just parsed can be assigned to. Parentheses
+// are allowed around it, so `(a) = 1` is fine.
+fn is_assign_target(tree: &AstNodeVec) -> bool {
+    let mut index = tree.len() - 1;
+    loop {
+        match tree.kind[index] {
+            AstNodeKind::ExprWord => return true,
+            AstNodeKind::ExprMember | AstNodeKind::ExprPrivateMember => return true,
+            AstNodeKind::ExprIndex => return true,
+            AstNodeKind::ExprParen => index -= 1,
+            _ => return false,
+        }
+    }
+}
+
+// The grammar forbids some operands without parentheses, even though
+// precedence would give them a meaning: `??` mixed with `||` or `&&`, and a
+// unary operator on the left of `**`.
+fn check_operands(tree: &AstNodeVec, op: AstNodeKind) -> Result<(), String> {
+    use AstNodeKind::*;
+
+    let right = tree.len() - 1;
+    let left = right - tree.subtree_size[right] as usize;
+    let (left, right) = (tree.kind[left], tree.kind[right]);
+
+    let mixes_nullish = match op {
+        ExprNullish => [left, right]
+            .iter()
+            .any(|k| matches!(k, ExprBoolOr | ExprBoolAnd)),
+        ExprBoolOr | ExprBoolAnd => [left, right].contains(&ExprNullish),
+        _ => false,
+    };
+    if mixes_nullish {
+        return Err(format!(
+            "`??` can't be mixed with `||` or `&&` without parentheses"
+        ));
+    }
+
+    let unary_left = matches!(
+        left,
+        ExprNot | ExprBinNot | ExprPositive | ExprNegative | ExprTypeof | ExprVoid | ExprDelete
+    );
+    if op == ExprExp && unary_left {
+        return Err(format!(
+            "unary operator on the left of `**` needs parentheses"
+        ));
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::*;
+    use crate::util::*;
+
+    #[test_resources("test/easy/*")]
+    fn parse_easy(path: &str) {
+        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+
+        let doc = match extract_yaml(&source) {
+            None => return,
+            Some(d) => d,
+        };
+        let expected_token_string = match doc["ast"].as_str() {
+            None => return,
+            Some(s) => s,
+        };
+
+        let mut symbols = Symbols::new();
+        let tokens = lex(&source, &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let ast = parse(&tokens, &symbols).expect("doesn't error");
+
+        let mut output = Vec::new();
+        for token in &ast {
+            println!("{:?}", token.to_owned());
+
+            output.push(format!("{:?}", token.kind));
+        }
+
+        let mut expected_tokens = Vec::with_capacity(output.len());
+        expected_tokens.push(AstNodeKind::UtilSentinel.into());
+        for token in expected_token_string.trim().split(",") {
+            let token = token.trim();
+            expected_tokens.push(token);
+        }
+
+        assert_eq!(&output, &expected_tokens);
+    }
+
+    #[test]
+    fn parse_asi() {
+        let parses = |source: &str| {
+            let mut symbols = Symbols::new();
+            let tokens = lex(source, &mut symbols)
+                .map_err(|e| e.diagnostics)
+                .expect("doesn't error");
+
+            return parse(&tokens, &symbols).is_ok();
+        };
+
+        assert!(parses("a\nb"));
+        assert!(parses("a /* \n */ b"));
+        assert!(parses("a // b\nc"));
+        assert!(parses("{ a }"));
+        assert!(parses("a; b"));
+
+        assert!(!parses("a b"));
+        assert!(!parses("a /* */ b"));
+    }
+
+    // Prints the tree as s-expressions, so tests can check its shape and not
+    // just the order of the nodes.
+    fn shape(source: &str) -> Result<String, String> {
+        let mut symbols = Symbols::new();
+        let tokens = lex(source, &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let tree = parse(&tokens, &symbols)?;
+
+        fn print(tree: &AstNodeVec, symbols: &Symbols, index: usize, out: &mut String) {
+            let kind = tree.kind[index];
+            use AstNodeKind::{ExprWord, PatKey, PatWord, TypeKeyword, TypeRef};
+            if let ExprWord | PatWord | PatKey | TypeRef | TypeKeyword = kind {
+                out.push_str(symbols.to_str(tree.extra[index]).unwrap());
+                if kind == PatKey {
+                    out.push(':');
+                }
+                return;
+            }
+
+            let mut children = Vec::new();
+            let mut child = index;
+            while child > index + 1 - tree.subtree_size[index] as usize {
+                child -= 1;
+                children.push(child);
+                child -= tree.subtree_size[child] as usize - 1;
+            }
+
+            let name: &str = kind.into();
+            if children.is_empty() {
+                out.push_str(name);
+                return;
+            }
+
+            out.push('(');
+            out.push_str(name);
+            for &child in children.iter().rev() {
+                out.push(' ');
+                print(tree, symbols, child, out);
+            }
+            if matches!(
+                kind,
+                AstNodeKind::ExprMember | AstNodeKind::ExprPrivateMember
+            ) {
+                out.push_str(" .");
+                out.push_str(symbols.to_str(tree.extra[index]).unwrap());
+            }
+            out.push(')');
+        }
+
+        let mut output = String::new();
+        print(&tree, &symbols, tree.len() - 1, &mut output);
+
+        return Ok(output);
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let shape = |source| shape(source).expect("doesn't error");
+
+        assert_eq!(
+            shape("a + b * c ** d ?? e"),
+            "(ExprNullish (ExprAdd a (ExprMult b (ExprExp c d))) e)"
+        );
+        assert_eq!(shape("a - b - c"), "(ExprSub (ExprSub a b) c)");
+        assert_eq!(
+            shape("a ?? null ?? false"),
+            "(ExprNullish (ExprNullish a ExprNull) ExprBoolean)"
+        );
+        assert_eq!(
+            shape("x === null && !false"),
+            "(ExprBoolAnd (ExprEqEqEq x ExprNull) (ExprNot ExprBoolean))"
+        );
+        assert_eq!(shape("a = false"), "(ExprAssign a ExprBoolean)");
+
+        let mut symbols = Symbols::new();
+        let tokens = lex("true, false", &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .unwrap();
+        let tree = parse(&tokens, &symbols).unwrap();
+        assert_eq!(tree.kind[1], AstNodeKind::ExprBoolean);
+        assert_eq!((tree.extra[1], tree.extra[2]), (1, 0));
+
+        assert_eq!(shape("a ** b ** c"), "(ExprExp a (ExprExp b c))");
+        assert_eq!(
+            shape("a || b && c | d ^ e & f == g < h << i"),
+            "(ExprBoolOr a (ExprBoolAnd b (ExprBinOr c (ExprBinXor d (ExprBinAnd e (ExprEqEq f (ExprLt g (ExprShl h i))))))))"
+        );
+        assert_eq!(shape("a = b += c"), "(ExprAssign a (ExprAddEq b c))");
+        assert_eq!(
+            shape("a ? b : c ? d : e"),
+            "(ExprConditional a b (ExprConditional c d e))"
+        );
+        assert_eq!(
+            shape("a = b ? c = d : e, f"),
+            "(ExprComma (ExprAssign a (ExprConditional b (ExprAssign c d) e)) f)"
+        );
+        assert_eq!(
+            shape("!-a++ * typeof --b"),
+            "(ExprMult (ExprNot (ExprNegative (ExprPostIncr a))) (ExprTypeof (ExprPreDecr b)))"
+        );
+        assert_eq!(
+            shape("(a + b) * c"),
+            "(ExprMult (ExprParen ExprParenIntro (ExprAdd a b)) c)"
+        );
+        assert_eq!(shape("a\n++b"), "(ExprPreIncr b)");
+        assert_eq!(
+            shape("(-a) ** b"),
+            "(ExprExp (ExprParen ExprParenIntro (ExprNegative a)) b)"
+        );
+        assert_eq!(shape("a ** -b"), "(ExprExp a (ExprNegative b))");
+        assert_eq!(
+            shape("(a) = b"),
+            "(ExprAssign (ExprParen ExprParenIntro a) b)"
+        );
+        assert_eq!(
+            shape("(a ?? b) || c"),
+            "(ExprBoolOr (ExprParen ExprParenIntro (ExprNullish a b)) c)"
+        );
+
+        assert!(shape_fails("a ?? b || c"));
+        assert!(shape_fails("a || b ?? c"));
+        assert!(shape_fails("a ?? b && c"));
+        assert!(shape_fails("-a ** b"));
+        assert!(shape_fails("a + b = c"));
+        assert!(shape_fails("++a++"));
+        assert!(shape_fails("a ? b"));
+        assert!(shape_fails("a +"));
+        assert!(shape_fails("(a"));
+    }
+
+    #[test]
+    fn parse_member_chains() {
+        let shape = |source| shape(source).expect("doesn't error");
+
+        assert_eq!(
+            shape("a.b[c](d, ...e,).if"),
+            "(ExprMember (ExprCall (ExprIndex (ExprMember a .b) c) d (ExprSpread e)) .if)"
+        );
+        assert_eq!(shape("a.#b"), "(ExprPrivateMember a .#b)");
+        assert_eq!(shape("this.#a"), "(ExprPrivateMember ExprThis .#a)");
+        assert_eq!(
+            shape("this.a?.()"),
+            "(ExprOptChain (ExprCall (ExprOptional (ExprMember ExprThis .a))))"
+        );
+        assert_eq!(shape("super.m()"), "(ExprCall (ExprMember ExprSuper .m))");
+        assert_eq!(shape("super(a)"), "(ExprCall ExprSuper a)");
+        assert_eq!(
+            shape("new a.b(c).d()"),
+            "(ExprCall (ExprMember (ExprNew (ExprMember a .b) c) .d))"
+        );
+        assert_eq!(shape("new a"), "(ExprNew a)");
+        assert_eq!(shape("new new a()()"), "(ExprNew (ExprNew a))");
+        assert_eq!(shape("new a()()"), "(ExprCall (ExprNew a))");
+        assert_eq!(shape("new.target"), "ExprNewTarget");
+        assert_eq!(shape("target"), "target");
+        assert!(shape_fails("new.x"));
+        assert!(shape_fails("super"));
+        assert_eq!(
+            shape("a?.b.c(d)"),
+            "(ExprOptChain (ExprCall (ExprMember (ExprMember (ExprOptional a) .b) .c) d))"
+        );
+        assert_eq!(
+            shape("a?.[b]?.(c)"),
+            "(ExprOptChain (ExprCall (ExprOptional (ExprIndex (ExprOptional a) b)) c))"
+        );
+        assert_eq!(
+            shape("(a?.b).c"),
+            "(ExprMember (ExprParen ExprParenIntro (ExprOptChain (ExprMember (ExprOptional a) .b))) .c)"
+        );
+        assert_eq!(
+            shape("a.b`x${c}y`"),
+            "(ExprTaggedTemplate (ExprMember a .b) (ExprTemplate ExprTemplateIntro c ExprTemplatePiece))"
+        );
+        assert_eq!(shape("`x`"), "(ExprTemplate ExprTemplateIntro)");
+        assert_eq!(
+            shape("-a.b++"),
+            "(ExprNegative (ExprPostIncr (ExprMember a .b)))"
+        );
+        assert_eq!(
+            shape("a.b = c[d] = e"),
+            "(ExprAssign (ExprMember a .b) (ExprAssign (ExprIndex c d) e))"
+        );
+
+        assert!(shape_fails("a?.b = c"));
+        assert!(shape_fails("a?.b`c`"));
+        assert!(shape_fails("new a?.b()"));
+        assert!(shape_fails("a(b c)"));
+        assert!(shape_fails("a[b"));
+        assert!(shape_fails("a.1"));
+        assert!(shape_fails("new.foo"));
+    }
+
+    #[test]
+    fn parse_declarations() {
+        let shape = |source| shape(source).expect("doesn't error");
+
+        assert_eq!(
+            shape("let a: number = 1, b"),
+            "(StmtLet (StmtVarDecl a number ExprNumber) (StmtVarDecl b))"
+        );
+        assert_eq!(
+            shape("const [a, , ...b] = c"),
+            "(StmtConst (StmtVarDecl (PatArray a PatHole (PatRest b)) c))"
+        );
+        assert_eq!(
+            shape("var { a, b: [c = 1], [d]: e, if: f, ...g } = h"),
+            "(StmtVar (StmtVarDecl (PatObject a (PatProp b: (PatArray (PatDefault c ExprNumber))) (PatProp d e) (PatProp if: f) (PatRest g)) h))"
+        );
+        assert_eq!(
+            shape("let { 'a': b, 1: c, 0x2: d = 3 } = e"),
+            "(StmtLet (StmtVarDecl (PatObject (PatProp PatStringKey b) (PatProp PatNumberKey c) (PatProp PatNumberKey (PatDefault d ExprNumber))) e))"
+        );
+        assert_eq!(
+            shape("let { a = b } = c"),
+            "(StmtLet (StmtVarDecl (PatObject (PatDefault a b)) c))"
+        );
+        assert_eq!(
+            shape("let [,] = a"),
+            "(StmtLet (StmtVarDecl (PatArray PatHole) a))"
+        );
+        assert_eq!(shape("let a!: T"), "(StmtLet (StmtVarDecl a T))");
+        assert_eq!(
+            shape("let a: A | B & C[][] | null"),
+            "(StmtLet (StmtVarDecl a (TypeUnion (TypeUnion A (TypeIntersection B (TypeArray (TypeArray C)))) null)))"
+        );
+        assert_eq!(
+            shape("let a: Map<A, Array<B<C>>>[]"),
+            "(StmtLet (StmtVarDecl a (TypeArray (TypeGeneric Map A (TypeGeneric Array (TypeGeneric B C))))))"
+        );
+        assert_eq!(
+            shape("let a: (A | B)[]"),
+            "(StmtLet (StmtVarDecl a (TypeArray (TypeUnion A B))))"
+        );
+        assert_eq!(
+            shape("let a: 'x' | 1"),
+            "(StmtLet (StmtVarDecl a (TypeUnion TypeString TypeNumber)))"
+        );
+        assert_eq!(
+            shape("let a: Array<number>= x, b: A<B<C>>= d, c: A<B<C<D>>>= e"),
+            "(StmtLet (StmtVarDecl a (TypeGeneric Array number) x) (StmtVarDecl b (TypeGeneric A (TypeGeneric B C)) d) (StmtVarDecl c (TypeGeneric A (TypeGeneric B (TypeGeneric C D))) e))"
+        );
+
+        assert!(shape_fails("const a"));
+        assert!(shape_fails("let [a]"));
+        assert!(shape_fails("let a!: T = 1"));
+        assert!(shape_fails("let a!: A<B>= 1"));
+        assert!(shape_fails("let a: A<B>>= 1"));
+        assert!(shape_fails("let a! = 1"));
+        assert!(shape_fails("let [a]!: T = b"));
+        assert!(shape_fails("let [...a,] = b"));
+        assert!(shape_fails("let { ...[a] } = b"));
+        assert!(shape_fails("let a: A<B>> = c"));
+        assert!(shape_fails("let a: A<B"));
+        assert!(shape_fails("let 1 = a"));
+        assert!(shape_fails("let a b"));
+    }
+
+    #[test]
+    fn parse_functions() {
+        let shape = |source| shape(source).expect("doesn't error");
+
+        assert_eq!(
+            shape("function f(a, b = 1, ...c) { return a }"),
+            "(ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro (PatParam a) (PatParam b ExprNumber) (PatParam (PatRest c))) (StmtBlock StmtBlockIntro (StmtReturn a)))"
+        );
+        assert_eq!(
+            shape("function f(this: T, a?: A, { b }: B = c): R {}"),
+            "(ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro (PatParam PatThis T) (PatParam a A) (PatParam (PatObject b) B c)) R (StmtBlock StmtBlockIntro))"
+        );
+        assert_eq!(
+            shape("function f(a: A<B>= c) {}"),
+            "(ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro (PatParam a (TypeGeneric A B) c)) (StmtBlock StmtBlockIntro))"
+        );
+        assert_eq!(
+            shape("a = function () { return\nb }"),
+            "(ExprAssign a (ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro) (StmtBlock StmtBlockIntro StmtReturn b)))"
+        );
+        assert_eq!(
+            shape("function f() { function g() { return; } return g }"),
+            "(ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro) (StmtBlock StmtBlockIntro (ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro) (StmtBlock StmtBlockIntro StmtReturn)) (StmtReturn g)))"
+        );
+
+        assert_eq!(
+            shape("async function f() {}"),
+            "(ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro) (StmtBlock StmtBlockIntro))"
+        );
+        assert_eq!(
+            shape("x = async function () { return }"),
+            "(ExprAssign x (ExprFunction ExprFunctionIntro (ExprParams ExprParamsIntro) (StmtBlock StmtBlockIntro StmtReturn)))"
+        );
+
+        let mut symbols = Symbols::new();
+        let tokens = lex("async function f() {}", &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .unwrap();
+        let tree = parse(&tokens, &symbols).unwrap();
+        assert_eq!(tree.extra[1], symbols.from_str("f").unwrap());
+        assert_eq!(tree.kind.last(), Some(&AstNodeKind::ExprFunction));
+        assert_eq!(tree.extra.last(), Some(&1));
+
+        let mut symbols = Symbols::new();
+        let tokens = lex("function f(a?) {}", &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .unwrap();
+        let tree = parse(&tokens, &symbols).unwrap();
+        assert_eq!(tree.extra[1], symbols.from_str("f").unwrap());
+        let param = tree
+            .kind
+            .iter()
+            .position(|&k| k == AstNodeKind::PatParam)
+            .unwrap();
+        assert_eq!(tree.extra[param], 1);
+
+        assert!(shape_fails("function () {}"));
+        assert!(shape_fails("async function () {}"));
+        assert!(shape_fails("return a"));
+        assert!(shape_fails("function f() {} return"));
+        assert!(shape_fails("function f(a, this) {}"));
+        assert!(shape_fails("function f(a? = 1) {}"));
+        assert!(shape_fails("function f(...a = 1) {}"));
+        assert!(shape_fails("function f(...a?) {}"));
+        assert!(shape_fails("function f(a b) {}"));
+        assert!(shape_fails("function f()"));
+        assert!(shape_fails("function f(): A<B>> {}"));
+    }
+
+    #[test]
+    fn parse_arrows() {
+        let shape = |source| shape(source).expect("doesn't error");
+
+        assert_eq!(
+            shape("a => a + 1"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a)) (ExprAdd a ExprNumber))"
+        );
+        assert_eq!(
+            shape("(a, b = c + 1) => { return a }"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a) (PatParam b (ExprAdd c ExprNumber))) (StmtBlock StmtBlockIntro (StmtReturn a)))"
+        );
+        assert_eq!(
+            shape("() => a"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro) a)"
+        );
+        assert_eq!(
+            shape("(a: T, b?, ...c): R => a"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a T) (PatParam b) (PatParam (PatRest c))) R a)"
+        );
+        assert_eq!(
+            shape("(a, { b }) => a"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a) (PatParam (PatObject b))) a)"
+        );
+        assert_eq!(
+            shape("async (a) => a"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a)) a)"
+        );
+        assert_eq!(
+            shape("async a => a"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a)) a)"
+        );
+        assert_eq!(
+            shape("<T extends A<B>, U,>(a: T): U => a"),
+            "(ExprArrow ExprArrowIntro (TypeParams (TypeParam (TypeGeneric A B)) TypeParam) (ExprParams ExprParamsIntro (PatParam a T)) U a)"
+        );
+        assert_eq!(
+            shape("f(a => b, (c) => d)"),
+            "(ExprCall f (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam a)) b) (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam c)) d))"
+        );
+        assert_eq!(
+            shape("a = b => c = d"),
+            "(ExprAssign a (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam b)) (ExprAssign c d)))"
+        );
+        assert_eq!(
+            shape("a ? b => c : (d) => e"),
+            "(ExprConditional a (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam b)) c) (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam d)) e))"
+        );
+        assert_eq!(
+            shape("a || (b => c)"),
+            "(ExprBoolOr a (ExprParen ExprParenIntro (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam b)) c)))"
+        );
+
+        // Still parenthesized expressions without a `=>`.
+        assert_eq!(
+            shape("(a, b)"),
+            "(ExprParen ExprParenIntro (ExprComma a b))"
+        );
+        assert_eq!(
+            shape("a ? (b) : c"),
+            "(ExprConditional a (ExprParen ExprParenIntro b) c)"
+        );
+        assert_eq!(
+            shape("a ? (b) : c => d"),
+            "(ExprConditional a (ExprParen ExprParenIntro b) (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam c)) d))"
+        );
+        assert_eq!(
+            shape("a ? (b): C => d : e"),
+            "(ExprConditional a (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam b)) C d) e)"
+        );
+        assert_eq!(
+            shape("a ? (b): C => d ? e : f : g"),
+            "(ExprConditional a (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam b)) C (ExprConditional d e f)) g)"
+        );
+        assert_eq!(
+            shape("a ? f((b): C => d) : e"),
+            "(ExprConditional a (ExprCall f (ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam b)) C d)) e)"
+        );
+        assert_eq!(
+            shape("([a, b]) => a"),
+            "(ExprArrow ExprArrowIntro (ExprParams ExprParamsIntro (PatParam (PatArray a b))) a)"
+        );
+        assert_eq!(
+            shape("(a)\n(b)"),
+            "(ExprCall (ExprParen ExprParenIntro a) b)"
+        );
+
+        let mut symbols = Symbols::new();
+        let tokens = lex("async () => { return 1 }", &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .unwrap();
+        let tree = parse(&tokens, &symbols).unwrap();
+        assert_eq!(tree.kind[1], AstNodeKind::ExprArrowIntro);
+        assert_eq!(tree.extra[1], 1);
+
+        assert!(shape_fails("(a: T)"));
+        assert!(shape_fails("([1])"));
+        assert!(shape_fails("({ a: 1 })"));
+        assert!(shape_fails("()"));
+        assert!(shape_fails("(a,)"));
+        assert!(shape_fails("(...a, b) => a"));
+        assert!(shape_fails("(a + b) => a"));
+        assert!(shape_fails("((a)) => a"));
+        assert!(shape_fails("(a)\n=> a"));
+        assert!(shape_fails("() => {} + 1"));
+        assert!(shape_fails("() => {}.a"));
+        assert!(shape_fails("<>() => a"));
+        assert!(shape_fails("<T>a => a"));
+        assert!(shape_fails("(a.b = 1) => a"));
+        assert!(shape_fails("(a[0] = 1) => a"));
+        assert!(shape_fails("a + b => c"));
+        assert!(shape_fails("a || (b) => c"));
+        assert!(shape_fails("!a => b"));
+        assert!(shape_fails("typeof async () => a"));
+        assert!(shape_fails("new a => b"));
+        assert!(shape_fails("a * <T>() => b"));
+        assert!(shape_fails("() => {} ? a : b"));
+        assert!(shape_fails("() => {} = a"));
+        assert!(shape_fails("x = <any>y"));
+        assert!(shape_fails("x = <A<B>>y"));
+    }
+
+    fn shape_fails(source: &str) -> bool {
+        return shape(source).is_err();
     }
 }
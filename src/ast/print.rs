@@ -30,14 +30,18 @@ impl NotationBuilder {
 
         let notation = match node.kind {
             ExprWord => Notation::txt("word"),
+            ExprBoolean if *node.extra == 0 => Notation::txt("false"),
             ExprBoolean => Notation::txt("true"),
+            ExprNull => Notation::txt("null"),
+            ExprThis => Notation::txt("this"),
+            ExprSuper => Notation::txt("super"),
 
             StmtIfIntro => return None,
             StmtBlockIntro => return None,
             StmtBlock => 'end: {
                 let children = self.collect_tree(*node.subtree_size);
 
-                if children.len() == 0 {
+                if children.is_empty() {
                     break 'end Notation::braced(NOTE & "{" & NL & "}" & DONE);
                 }
 
@@ -134,12 +138,13 @@ mod tests {
             LexOptions {
                 include_comments: true,
                 include_spacing: false,
+                ..Default::default()
             },
         )
-        .map_err(|e| e.error)
+        .map_err(|e| e.diagnostics)
         .expect("doesn't error");
 
-        let ast = parse(&tokens).expect("doesn't error");
+        let ast = parse(&tokens, &symbols).expect("doesn't error");
 
         // println!(
         //     "{:?}",
@@ -156,6 +161,7 @@ mod tests {
         pretty_assertions::assert_eq!(source, output);
     }
 
+    #[cfg(feature = "simd")]
     #[bench]
     fn wadler_bench(bencher: &mut Bencher) {
         let source = std::fs::read_to_string("test/benches/print.ts")
@@ -169,12 +175,13 @@ mod tests {
                 LexOptions {
                     include_comments: true,
                     include_spacing: false,
+                    ..Default::default()
                 },
             )
-            .map_err(|e| e.error)
+            .map_err(|e| e.diagnostics)
             .expect("doesn't error");
 
-            let ast = parse(&tokens).expect("doesn't error");
+            let ast = parse(&tokens, &symbols).expect("doesn't error");
 
             let mut builder = NotationBuilder::default();
             let notation = builder.build(&ast);
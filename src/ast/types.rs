@@ -7,23 +7,160 @@ pub enum AstNodeKind {
     // Expressions
     ExprString,
     ExprNumber,
+    // The extra is 1 for `true` and 0 for `false`.
     ExprBoolean,
+    ExprNull,
+    ExprThis,
+    // Only ever the object of a member access or the callee of a call.
+    ExprSuper,
     ExprWord,
 
+    // The intro's extra is the id of the template's first piece, and each
+    // substitution is followed by a piece with the id of the next one.
     ExprTemplateIntro,
+    ExprTemplatePiece,
     ExprTemplate,
+    ExprTaggedTemplate,
 
+    // The intro, the params, an optional return type, then the body's
+    // `StmtBlockIntro`..`StmtBlock`. The intro's extra is the name's symbol,
+    // or 0, and the end's extra is 1 for an `async` function.
     ExprFunctionIntro,
     ExprFunction,
 
+    // The intro's extra is 1 for an `async` arrow. Then optional
+    // `TypeParams`, the params, an optional return type, and a body that's
+    // either a block or an expression.
+    ExprArrowIntro,
+    ExprArrow,
+
     ExprParenIntro,
     ExprParen,
 
+    // Member accesses and calls. `a.b` is `ExprWord,ExprMember`, with the
+    // name's symbol as the extra, and `f(a, ...b)` is
+    // `ExprWord,ExprWord,ExprWord,ExprSpread,ExprCall`.
+    ExprMember,
+    ExprPrivateMember,
+    ExprIndex,
+    ExprCall,
+    ExprSpread,
+    ExprNew,
+    ExprNewTarget,
+
+    // In `a?.b.c`, `ExprOptional` wraps `a` and skips to the end of the
+    // enclosing `ExprOptChain` when it's nullish, so the whole thing is
+    // `ExprWord,ExprOptional,ExprMember,ExprMember,ExprOptChain`.
+    ExprOptional,
+    ExprOptChain,
+
+    // Operators. These come after their operands, so `a + b` is
+    // `ExprWord,ExprWord,ExprAdd`.
+    ExprComma,
+    ExprConditional,
+
+    ExprAssign,
+    ExprAddEq,
+    ExprSubEq,
+    ExprMultEq,
+    ExprDivEq,
+    ExprModEq,
+    ExprExpEq,
+    ExprShlEq,
+    ExprShrEq,
+    ExprUShrEq,
+    ExprBinAndEq,
+    ExprBinOrEq,
+    ExprBinXorEq,
+    ExprBoolAndEq,
+    ExprBoolOrEq,
+    ExprNullishEq,
+
+    ExprNullish,
+    ExprBoolOr,
+    ExprBoolAnd,
+    ExprBinOr,
+    ExprBinXor,
+    ExprBinAnd,
+    ExprEqEq,
+    ExprNeq,
+    ExprEqEqEq,
+    ExprNeqEq,
+    ExprLt,
+    ExprGt,
+    ExprLeq,
+    ExprGeq,
+    ExprInstanceof,
+    ExprIn,
+    ExprShl,
+    ExprShr,
+    ExprUShr,
+    ExprAdd,
+    ExprSub,
+    ExprMult,
+    ExprDiv,
+    ExprMod,
+    ExprExp,
+
+    ExprNot,
+    ExprBinNot,
+    ExprPositive,
+    ExprNegative,
+    ExprTypeof,
+    ExprVoid,
+    ExprDelete,
+    ExprPreIncr,
+    ExprPreDecr,
+    ExprPostIncr,
+    ExprPostDecr,
+
     // Not an expr, but... sort of one. Maybe.
     ExprParamsIntro,
     ExprParams,
 
+    // Binding patterns, as in `let [a, , ...b] = c`. Object properties are
+    // a key, then the value's pattern; `{ a }` is just a `PatWord`. Keys are
+    // a `PatKey` with the name's symbol as its extra, a `PatStringKey` or
+    // `PatNumberKey` with the literal's id, or a computed key expression.
+    PatWord,
+    PatArray,
+    PatHole,
+    PatObject,
+    PatProp,
+    PatKey,
+    PatStringKey,
+    PatNumberKey,
+    PatDefault,
+    PatRest,
+    // A function parameter: a pattern, then an optional type and an optional
+    // default, like `StmtVarDecl`. The extra is 1 for an optional parameter.
+    PatParam,
+    PatThis,
+
+    // Type annotations. `TypeRef` and `TypeKeyword` have the name's symbol as
+    // their extra, and `A<B>` is `TypeRef,TypeRef,TypeGeneric`.
+    TypeRef,
+    TypeGeneric,
+    TypeKeyword,
+    TypeString,
+    TypeNumber,
+    TypeArray,
+    TypeUnion,
+    TypeIntersection,
+    // `<T extends U>`; each `TypeParam` has the name's symbol as its extra
+    // and the constraint, if any, as its child.
+    TypeParam,
+    TypeParams,
+
     // Statements
+    StmtVar,
+    StmtLet,
+    StmtConst,
+    // A pattern, then an optional type and an optional initializer. The
+    // extra is 1 for a definite assignment, like `let a!: T`.
+    StmtVarDecl,
+    StmtReturn,
+
     StmtIfIntro,
     StmtIf,
 
@@ -55,7 +192,7 @@ const fn check_astnodekind_size() {
 const _: () = check_astnodekind_size();
 
 impl AstNodeVec {
-    pub fn postorder(&self) -> AstTraversal {
+    pub fn postorder(&self) -> AstTraversal<'_> {
         let mut traversal = AstTraversal {
             tree: self,
             tree_stack: Vec::new(),
@@ -63,7 +200,7 @@ impl AstNodeVec {
         };
 
         let mut index = self.len() - 1;
-        let final_index = 0 as usize;
+        let final_index = 0;
 
         while index > final_index {
             let node = traversal.tree.get(index).unwrap();
@@ -81,7 +218,7 @@ impl AstNodeVec {
         return traversal;
     }
 
-    pub fn preorder(&self) -> AstTraversal {
+    pub fn preorder(&self) -> AstTraversal<'_> {
         let mut traversal = AstTraversal {
             tree: self,
             tree_stack: Vec::new(),
@@ -89,7 +226,7 @@ impl AstNodeVec {
         };
 
         let mut index = self.len() - 1;
-        let final_index = 0 as usize;
+        let final_index = 0;
 
         while index > final_index {
             let node = traversal.tree.get(index).unwrap();
@@ -241,7 +378,7 @@ mod tests {
             }
 
             for node in &postorder {
-                ast.push(node.clone());
+                ast.push(*node);
             }
 
             return Traversals {
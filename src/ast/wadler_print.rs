@@ -198,19 +198,19 @@ pub struct Chunk<'a> {
 
 impl<'a> Chunk<'a> {
     fn with_notation(self, notation: &'a Notation) -> Chunk<'a> {
-        let mut ret = self.clone();
+        let mut ret = self;
         ret.notation = notation;
         return ret;
     }
 
     fn indented(self, indent: u32) -> Chunk<'a> {
-        let mut ret = self.clone();
+        let mut ret = self;
         ret.indent += indent;
         return ret;
     }
 
     fn flat(self) -> Chunk<'a> {
-        let mut ret = self.clone();
+        let mut ret = self;
         ret.flat = true;
         return ret;
     }
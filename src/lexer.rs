@@ -1,30 +1,113 @@
+use crate::literals::{cook_string, decode_jsx_entities, parse_number, template_raw};
+use crate::scan::{DefaultScanner, Scanner};
 use crate::tokens::*;
 use crate::util::*;
 use std::ops::*;
-use std::simd::prelude::*;
+use strum::IntoStaticStr;
 
 #[derive(Default, Clone, Copy)]
 pub struct LexOptions {
+    /// Also controls whether there's a `Hashbang` token.
     pub include_comments: bool,
     pub include_spacing: bool,
+    /// Keep going after an error, using `Unknown` or partial tokens for the
+    /// bad text, so that the whole file gets tokens. Every problem ends up in
+    /// `LexResult::diagnostics`.
+    pub recover: bool,
+    /// Lex JSX elements, for `.tsx` files. Like a regex, whether a `<` starts
+    /// an element is guessed from the previous token. This is fixed for the
+    /// whole file: the lexer runs ahead of the parser, so there's no way for
+    /// the parser to switch it for e.g. a type argument list it knows about.
+    pub jsx: bool,
+    pub file: FileOptions,
+}
+
+/// Syntax that's allowed or not depending on what kind of file it is.
+#[derive(Clone, Copy)]
+pub struct FileOptions {
+    /// A UTF-8 byte order mark at the start of the file, which gets skipped.
+    pub allow_bom: bool,
+    /// A `#!` line at the start of the file, or right after the BOM.
+    pub allow_hashbang: bool,
+    /// Annex B's `<!--` and `-->` line comments. These are only allowed in
+    /// scripts, not modules, and change the meaning of e.g. `a<!--b`.
+    pub allow_html_comments: bool,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        return Self {
+            allow_bom: true,
+            allow_hashbang: true,
+            allow_html_comments: false,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoStaticStr)]
+pub enum LexErrorCode {
+    FileTooLarge,
+    UnrecognizedCharacter,
+    InvalidIdentifier,
+    InvalidNumber,
+    NewlineInString,
+    InvalidEscape,
+    UnterminatedString,
+    UnterminatedTemplate,
+    UnterminatedRegex,
+    UnterminatedComment,
+    UnterminatedJsx,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LexDiagnostic {
+    pub code: LexErrorCode,
+    /// Byte offsets of the offending text, `start..end`.
+    pub start: u32,
+    pub end: u32,
+    pub message: String,
+}
+
+/// Lexing stopped early; the reason is the last entry in `LexState::diagnostics`.
+#[derive(Debug)]
+pub struct LexAbort;
+
+/// Something the lexer is inside of, which changes what comes next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LexContext {
+    /// A `${` substitution, counting the `{` that have been opened (and not
+    /// yet closed) within it. A `}` only resumes the template when this is 0.
+    Template(u32),
+    /// A JSX `{}` expression container, counting `{` the same way. Its `}`
+    /// goes back to the tag or children it's in.
+    JsxExpr(u32),
+    /// Between the `<` and `>` of a JSX tag. `closing` is for `</a>`, and
+    /// `self_closing` for `<a />`.
+    JsxTag { closing: bool, self_closing: bool },
+    /// Between an element's opening and closing tags.
+    JsxChildren,
 }
 
 #[derive(Default)]
 pub struct LexState {
     pub begin_index: usize,
     pub index: usize,
-    pub template_level: usize,
+    /// The templates and JSX we're currently inside of, innermost last.
+    pub context_stack: Vec<LexContext>,
+    /// Whether there's been a line break since the last non-trivia token.
+    pub newline_before: bool,
     pub tokens: TokenVec,
+    pub diagnostics: Vec<LexDiagnostic>,
     pub opts: LexOptions,
 }
 
 impl LexState {
     fn peek(&self, bytes: &[u8]) -> Option<u8> {
-        return bytes.get(self.index).map(|s| *s);
+        return bytes.get(self.index).copied();
     }
 
     fn peek_n<const N: usize>(&self, bytes: &[u8]) -> Option<[u8; N]> {
-        return bytes[self.index..].try_into().ok();
+        return bytes.get(self.index..self.index + N)?.try_into().ok();
     }
 
     fn incr(&mut self) {
@@ -41,7 +124,7 @@ impl LexState {
         return a;
     }
 
-    fn text<'a, 'b>(&'a self, bytes: &'b [u8]) -> &'b [u8] {
+    fn text<'b>(&self, bytes: &'b [u8]) -> &'b [u8] {
         return &bytes[self.span()];
     }
 
@@ -51,495 +134,1657 @@ impl LexState {
 
     fn add_token_cond(&mut self, kind: TokenKind, cond: bool) {
         if cond {
-            self.tokens.push(Token {
-                kind,
-                text_index: self.begin_index,
-                extra: self.index as u32,
-            });
+            self.add_token(kind);
         }
 
         self.begin_index = self.index;
     }
 
     fn add_token(&mut self, kind: TokenKind) {
-        self.tokens.push(Token {
-            kind,
-            text_index: self.begin_index,
-            extra: self.index as u32,
-        });
-
-        self.begin_index = self.index;
+        self.add_token_extra(kind, 0);
     }
 
     fn add_token_extra(&mut self, kind: TokenKind, extra: u32) {
+        let newline_before = self.newline_before;
+        if !kind.is_trivia() {
+            self.newline_before = false;
+        }
+
+        // `lex_with_options` checks that the text fits in a u32.
         self.tokens.push(Token {
             kind,
-            text_index: self.begin_index,
+            start: self.begin_index as u32,
+            end: self.index as u32,
             extra,
+            newline_before,
         });
 
         self.begin_index = self.index;
     }
 
-    fn peek_32(&self, bytes: &[u8]) -> Simd<u8, 32> {
-        return Simd::load_or_default(&bytes[self.index..]);
+    /// Adds a token for the first of `options` whose suffix comes next, or
+    /// `default` if none of them do. Longer suffixes should be listed first.
+    fn add_token_longest(
+        &mut self,
+        bytes: &[u8],
+        default: TokenKind,
+        options: &[(&[u8], TokenKind)],
+    ) {
+        let rest = bytes.get(self.index..).unwrap_or(&[]);
+        for &(suffix, kind) in options {
+            if rest.starts_with(suffix) {
+                self.incr_count(suffix.len());
+                self.add_token(kind);
+                return;
+            }
+        }
+
+        self.add_token(default);
+    }
+
+    fn regex_allowed(&self) -> bool {
+        return regex_allowed(&self.tokens.kind);
+    }
+
+    fn jsx_allowed(&self, bytes: &[u8]) -> bool {
+        return jsx_allowed(&self.tokens.kind, bytes, self.index);
+    }
+
+    // Records a problem with the text in `begin_index..index`. When we're
+    // recovering, the caller should add a token for that text and keep going.
+    fn error(&mut self, code: LexErrorCode, message: String) -> Result<(), LexAbort> {
+        return self.error_at(code, self.begin_index..self.index, message);
+    }
+
+    fn error_at(
+        &mut self,
+        code: LexErrorCode,
+        span: Range<usize>,
+        message: String,
+    ) -> Result<(), LexAbort> {
+        self.diagnostics.push(LexDiagnostic {
+            code,
+            start: span.start as u32,
+            end: span.end as u32,
+            message,
+        });
+
+        if self.opts.recover {
+            return Ok(());
+        }
+
+        return Err(LexAbort);
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn finish(self) -> Result<TokenVec, LexResult> {
+        if !self.diagnostics.is_empty() {
+            return Err(LexResult {
+                tokens: self.tokens,
+                diagnostics: self.diagnostics,
+            });
+        }
+
+        return Ok(self.tokens);
     }
 
-    fn e(&mut self, r: Result<(), String>) -> Result<(), LexResult> {
+    #[allow(clippy::result_large_err)]
+    fn e<T>(&mut self, r: Result<T, LexAbort>) -> Result<T, LexResult> {
         match r {
-            Ok(()) => return Ok(()),
-            Err(e) => {
-                let tokens = core::mem::replace(&mut self.tokens, TokenVec::new());
+            Ok(value) => return Ok(value),
+            Err(LexAbort) => {
+                let tokens = core::mem::take(&mut self.tokens);
+                let diagnostics = core::mem::take(&mut self.diagnostics);
+
+                return Err(LexResult {
+                    tokens,
+                    diagnostics,
+                });
+            }
+        }
+    }
+}
+
+// A `/` after something that ends an expression is a divide; anywhere
+// else it starts a regex. This is the usual token-based approximation, so
+// e.g. `if (a) /re/.test(b)` still lexes as a divide.
+pub(crate) fn regex_allowed(kinds: &[TokenKind]) -> bool {
+    let kinds = kinds.iter().rev();
+    let Some(prev) = kinds.copied().find(|kind| !kind.is_trivia()) else {
+        return true;
+    };
+
+    match prev {
+        TokenKind::Key(key) => {
+            return !matches!(
+                key,
+                Key::This | Key::Super | Key::True | Key::False | Key::Null
+            );
+        }
+
+        TokenKind::Word
+        | TokenKind::PrivateName
+        | TokenKind::Number
+        | TokenKind::OctNumber
+        | TokenKind::HexNumber
+        | TokenKind::BinNumber
+        | TokenKind::BigInt
+        | TokenKind::String
+        | TokenKind::Regex
+        | TokenKind::StrTemplate
+        | TokenKind::StrTemplateEnd
+        | TokenKind::RParen
+        | TokenKind::RBracket
+        | TokenKind::PlusPlus
+        | TokenKind::MinusMinus => return false,
+
+        _ => return true,
+    }
+}
+
+// `<` starts an element in the same places a `/` starts a regex. TS's
+// `<T,>(x: T) => x` and `<T extends U>(x: T) => x` are how generic arrow
+// functions get written in `.tsx` files, so those aren't. Like TS, an
+// `extends` followed by `=`, `>` or `/` is an attribute instead.
+fn jsx_allowed(kinds: &[TokenKind], bytes: &[u8], index: usize) -> bool {
+    let rest = &bytes[index..];
+    match rest.first() {
+        Some(b'>') => {}
+        Some(b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'$' | 128..=255) => {
+            let len = rest.iter().position(|&c| ALNUM_MAP[c as usize] != 1);
+            let rest = &rest[len.unwrap_or(rest.len())..];
+            if rest.first() == Some(&b',') {
+                return false;
+            }
+
+            let skip_spaces = |rest: &[u8]| -> usize {
+                return rest.iter().take_while(|c| c.is_ascii_whitespace()).count();
+            };
 
-                return Err(LexResult { tokens, error: e });
+            let rest = &rest[skip_spaces(rest)..];
+            if let Some(after) = rest.strip_prefix(b"extends") {
+                let spaces = skip_spaces(after);
+                let next = after.get(spaces);
+                if spaces > 0 && !matches!(next, None | Some(b'=' | b'>' | b'/')) {
+                    return false;
+                }
             }
         }
+        _ => return false,
     }
+
+    return regex_allowed(kinds);
 }
 
+/// Carries the partial token list on purpose, so it's a big error type and
+/// the functions that return it allow `clippy::result_large_err`.
 pub struct LexResult {
     pub tokens: TokenVec,
-    pub error: String,
+    pub diagnostics: Vec<LexDiagnostic>,
 }
 
+#[allow(clippy::result_large_err)]
 pub fn lex(text: &str, symbols: &mut Symbols) -> Result<TokenVec, LexResult> {
     return lex_with_options(text, symbols, LexOptions::default());
 }
 
+#[allow(clippy::result_large_err)]
 pub fn lex_with_options(
     text: &str,
     symbols: &mut Symbols,
     opts: LexOptions,
 ) -> Result<TokenVec, LexResult> {
-    let mut state_data = LexState::default();
-    state_data.opts = opts;
-
-    let state = &mut state_data;
-    let bytes = text.as_bytes();
-
-    while let Some(byte) = state.pop(bytes) {
-        // Supposedly LLVM will automatically do the "computed-goto" trick here.
-        // We'll profile/disassemble later ig.
-        match byte {
-            b' ' | b'\t' => lex_whitespace(state, bytes, false),
-            b'\n' | b'\r' => lex_whitespace(state, bytes, true),
-
-            b'a'..=b'z' | b'A'..=b'Z' | b'_' => lex_word(state, bytes, symbols),
-            b'.' => lex_dot_with_suffix(state, bytes),
-            b'0' => lex_number_with_prefix(state, bytes),
-            b'1'..=b'9' => lex_number(state, bytes, false),
-
-            b'\'' => {
-                let result = lex_string(state, bytes, StringOpener::Quote);
-                state.e(result)?;
-            }
-            b'"' => {
-                let result = lex_string(state, bytes, StringOpener::DubQuote);
-                state.e(result)?;
-            }
-            b'`' => lex_template(state, bytes, true),
-
-            b'/' => lex_comment_or_div(state, bytes),
+    return lex_with_scanner::<DefaultScanner>(text, symbols, opts);
+}
 
-            b';' => state.add_token(TokenKind::Semicolon),
-            b':' => state.add_token(TokenKind::Colon),
+/// `lex_with_options`, but with a specific backend for the hot loops.
+#[allow(clippy::result_large_err)]
+pub fn lex_with_scanner<S: Scanner>(
+    text: &str,
+    symbols: &mut Symbols,
+    opts: LexOptions,
+) -> Result<TokenVec, LexResult> {
+    check_file_size(text)?;
 
-            b'[' => state.add_token(TokenKind::LBracket),
-            b']' => state.add_token(TokenKind::RBracket),
+    let mut state_data = LexState {
+        opts,
+        ..Default::default()
+    };
 
-            b'(' => state.add_token(TokenKind::LParen),
-            b')' => state.add_token(TokenKind::RParen),
+    let state = &mut state_data;
+    let bytes = text.as_bytes();
 
-            b'{' => state.add_token(TokenKind::LBrace),
-            b'}' => {
-                if state.template_level > 0 {
-                    lex_template(state, bytes, false);
-                }
+    lex_file_start::<S>(state, bytes);
 
-                state.add_token(TokenKind::RBrace)
-            }
+    loop {
+        let result = lex_next::<S>(state, bytes, symbols);
+        if !state.e(result)? {
+            break;
+        }
+    }
 
-            b'+' => {
-                if let Some(b'+') = state.peek(bytes) {
-                    state.incr();
-                    state.add_token(TokenKind::PlusPlus);
-                    continue;
-                } else {
-                    state.add_token(TokenKind::Add)
-                }
-            }
-            b'-' => {
-                if let Some(b'-') = state.peek(bytes) {
-                    state.incr();
-                    state.add_token(TokenKind::MinusMinus);
-                    continue;
-                } else {
-                    state.add_token(TokenKind::Sub)
-                }
-            }
+    let result = lex_file_end(state);
+    state.e(result)?;
 
-            b'*' => state.add_token(TokenKind::Mult),
+    return state_data.finish();
+}
 
-            _ => {
-                return Err(LexResult {
-                    tokens: state_data.tokens,
-                    error: (format!("unrecognized token: {} ({})", char::from(byte), byte)),
-                })
-            }
-        }
+#[allow(clippy::result_large_err)]
+pub(crate) fn check_file_size(text: &str) -> Result<(), LexResult> {
+    if text.len() > u32::MAX as usize {
+        return Err(LexResult {
+            tokens: TokenVec::new(),
+            diagnostics: vec![LexDiagnostic {
+                code: LexErrorCode::FileTooLarge,
+                start: 0,
+                end: 0,
+                message: format!("file is too large to lex ({} bytes)", text.len()),
+            }],
+        });
     }
 
-    return Ok(state_data.tokens);
+    return Ok(());
 }
 
-#[repr(u8)]
-pub enum StringOpener {
-    Quote = b'\'',
-    DubQuote = b'"',
+/// An edit that replaced `start..old_end` in the old text with what's at
+/// `start..new_end` in the new text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
 }
 
-const NEWLINE_SIMD: Simd<u8, 32> = Simd::from_array([b'\n'; 32]);
-const ZERO_SIMD: Simd<u8, 32> = Simd::from_array([0; 32]);
-
-pub fn lex_comment_or_div(state: &mut LexState, bytes: &[u8]) {
-    let star_filter = crate::simd::FilterShiftR::<1>::new(b'*');
-    const SLASH_SIMD: Simd<u8, 32> = Simd::from_array([b'/'; 32]);
-
-    match state.peek(bytes).unwrap_or(0) {
-        b'/' => loop {
-            let text = state.peek_32(bytes);
-            let newline_mask = text.simd_eq(NEWLINE_SIMD);
-            let zero_mask = text.simd_eq(ZERO_SIMD);
-            let end_mask = newline_mask | zero_mask;
+/// Lexes `new_text` by re-lexing only what `edit` could have changed, and
+/// reusing `old_tokens` and `old_diagnostics` for everything else. Those have
+/// to be what lexing `old_text` with `opts` gave.
+#[allow(clippy::result_large_err)]
+pub fn relex(
+    old_tokens: &TokenVec,
+    old_diagnostics: &[LexDiagnostic],
+    old_text: &str,
+    edit: TextEdit,
+    new_text: &str,
+    symbols: &mut Symbols,
+    opts: LexOptions,
+) -> Result<TokenVec, LexResult> {
+    type S = DefaultScanner;
 
-            if let Some(index) = end_mask.first_set() {
-                state.incr_count(index + 1);
+    check_file_size(new_text)?;
 
-                state.add_token_cond(TokenKind::LineComment, state.opts.include_comments);
+    let old_bytes = old_text.as_bytes();
+    let bytes = new_text.as_bytes();
 
-                break;
-            }
+    // A token can look past its end, e.g. `<` checking for `!--` or for the
+    // `T extends` of a generic arrow function, so this backs up past three
+    // more tokens than the edit touches. Starting right after a token that
+    // isn't trivia means there's no pending line break to worry about.
+    let touched = old_tokens
+        .end
+        .partition_point(|&end| (end as usize) < edit.start);
+    let before = (0..touched).rev();
+    let restart = before.filter(|&i| !old_tokens.kind[i].is_trivia()).nth(3);
+    let restart = restart.map_or(0, |i| i + 1);
 
-            state.incr_count(32);
-        },
+    let mut state_data = LexState {
+        opts,
+        ..Default::default()
+    };
 
-        b'*' => {
-            state.incr();
+    let state = &mut state_data;
+    state.tokens.extend(old_tokens.slice(0..restart).iter());
+
+    // Diagnostics are reused the same way as the tokens they're about.
+    let restart_offset = restart.checked_sub(1).map_or(0, |i| old_tokens.end[i]);
+    let kept = old_diagnostics.iter().filter(|d| d.start < restart_offset);
+    state.diagnostics.extend(kept.cloned());
+
+    // The templates and JSX we're inside of at the restart point only depend
+    // on the tokens before it.
+    let mut old_context = Vec::new();
+    for index in 0..restart {
+        replay_context(&mut old_context, old_tokens, index, old_bytes, &opts);
+    }
 
-            loop {
-                let text = state.peek_32(bytes);
+    state.context_stack = old_context.clone();
 
-                let zero_mask = text.simd_eq(ZERO_SIMD);
+    match restart {
+        0 => lex_file_start::<S>(state, bytes),
+        _ => {
+            state.index = old_tokens.end[restart - 1] as usize;
+            state.begin_index = state.index;
+        }
+    }
 
-                let star_mask = star_filter.check_eq(text);
-                let slash_mask = text.simd_eq(SLASH_SIMD);
+    let shift = |offset: u32| (offset as usize + edit.new_end - edit.old_end) as u32;
+    let mut old_index = restart;
 
-                let comment_end_mask = star_mask & slash_mask;
+    loop {
+        let count = state.tokens.len();
+        let result = lex_next::<S>(state, bytes, symbols);
+        if !state.e(result)? {
+            break;
+        }
 
-                match comment_end_mask.first_set() {
-                    None => {}
-                    Some(index) => {
-                        state.incr_count(index + 1);
+        if state.tokens.len() == count {
+            continue;
+        }
 
-                        state.add_token_cond(TokenKind::Comment, state.opts.include_comments);
-                        break;
-                    }
-                }
+        let token = state.tokens.index(count);
+        if token.kind.is_trivia() || (*token.start as usize) < edit.new_end {
+            continue;
+        }
 
-                if let Some(index) = zero_mask.first_set() {
-                    // This is technically an error.
-                    state.incr_count(index);
+        // Past the edit, once a token matches the old one at the same place
+        // and leaves us in the same context, the rest would match too.
+        let old_start = (*token.start as usize + edit.old_end - edit.new_end) as u32;
+        while old_tokens
+            .start
+            .get(old_index)
+            .is_some_and(|&start| start < old_start)
+        {
+            replay_context(&mut old_context, old_tokens, old_index, old_bytes, &opts);
+            old_index += 1;
+        }
 
-                    state.add_token_cond(TokenKind::Comment, state.opts.include_comments);
-                    break;
-                }
+        let Some(old) = old_tokens.get(old_index) else {
+            continue;
+        };
 
-                state.incr_count(31);
-            }
+        let same_token = *old.start == old_start
+            && old.kind == token.kind
+            && shift(*old.end) == *token.end
+            && old.newline_before == token.newline_before;
+        if !same_token {
+            continue;
         }
 
-        _ => {
-            state.add_token(TokenKind::Div);
+        replay_context(&mut old_context, old_tokens, old_index, old_bytes, &opts);
+        old_index += 1;
+
+        if old_context == state.context_stack {
+            let rest = old_tokens.slice(old_index..old_tokens.len());
+            state.tokens.extend(rest.iter().map(|token| {
+                let mut token = token.to_owned();
+                token.start = shift(token.start);
+                token.end = shift(token.end);
+                return token;
+            }));
+
+            let old_end = old_tokens.end[old_index - 1];
+            let after = old_diagnostics.iter().filter(|d| d.start >= old_end);
+            state.diagnostics.extend(after.map(|d| LexDiagnostic {
+                start: shift(d.start),
+                end: shift(d.end),
+                ..d.clone()
+            }));
+
+            return state_data.finish();
         }
     }
-}
-
-pub fn lex_string(state: &mut LexState, bytes: &[u8], opener: StringOpener) -> Result<(), String> {
-    const NEWLINE_MASK: Simd<u8, 32> = Simd::from_array([b'\n'; 32]);
-    let backslash_filter = crate::simd::FilterShiftR::<1>::new(b'\\');
-    let mask = Simd::from_array([opener as u8; 32]);
-
-    loop {
-        let text = state.peek_32(bytes);
-
-        let zero_mask = text.simd_eq(ZERO_SIMD);
-        let not_escaped_mask = backslash_filter.check_ne(text);
-        let quote_mask = text.simd_eq(mask);
-        let newline_mask = text.simd_eq(NEWLINE_MASK);
 
-        let quote_end_mask = quote_mask & not_escaped_mask;
-        let newline_end_mask = newline_mask & not_escaped_mask;
+    let result = lex_file_end(state);
+    state.e(result)?;
 
-        let error_end_mask = zero_mask | newline_end_mask;
+    return state_data.finish();
+}
 
-        match (quote_end_mask | error_end_mask).first_set() {
-            Some(index) => {
-                state.incr_count(index);
-            }
-            None => {
-                state.incr_count(31);
-                continue;
+// Updates `stack` the way lexing the token at `index` updated
+// `LexState::context_stack`.
+fn replay_context(
+    stack: &mut Vec<LexContext>,
+    tokens: &TokenVec,
+    index: usize,
+    bytes: &[u8],
+    opts: &LexOptions,
+) {
+    use LexContext::*;
+
+    let kind = tokens.kind[index];
+    let before = &tokens.kind[..index];
+
+    match (stack.last().copied(), kind) {
+        (Some(JsxChildren), TokenKind::Lt) => stack.push(JsxTag {
+            closing: false,
+            self_closing: false,
+        }),
+        (Some(JsxChildren | JsxTag { .. }), TokenKind::LBrace) => stack.push(JsxExpr(0)),
+        (
+            Some(JsxTag {
+                closing,
+                self_closing,
+            }),
+            TokenKind::Div,
+        ) => {
+            let after_lt = before.iter().rev().find(|kind| !kind.is_trivia());
+            let after_lt = after_lt == Some(&TokenKind::Lt);
+            *stack.last_mut().unwrap() = JsxTag {
+                closing: closing || after_lt,
+                self_closing: self_closing || !after_lt,
+            };
+        }
+        (
+            Some(JsxTag {
+                closing,
+                self_closing,
+            }),
+            TokenKind::Gt,
+        ) => {
+            stack.pop();
+            if closing {
+                stack.pop();
+            } else if !self_closing {
+                stack.push(JsxChildren);
             }
         }
+        (Some(JsxChildren | JsxTag { .. }), _) => {}
 
-        let (quote_first, error_first) = (
-            quote_end_mask.first_set().unwrap_or(33),
-            error_end_mask.first_set().unwrap_or(33),
-        );
-
-        if error_first < quote_first {
-            let zero_first = zero_mask.first_set().unwrap_or(32);
-            let newline_first = newline_end_mask.first_set().unwrap_or(32);
-            if zero_first < newline_first {
-                return Err(format!("File ended without finishing string"));
-            } else {
-                return Err(format!("String ended with newline instead of quote"));
+        (_, TokenKind::StrTemplateBegin) => stack.push(Template(0)),
+        (_, TokenKind::StrTemplateEnd) => {
+            stack.pop();
+        }
+        (Some(JsxExpr(0)), TokenKind::RBrace) => {
+            stack.pop();
+        }
+        (_, TokenKind::LBrace | TokenKind::RBrace) => {
+            if let Some(Template(depth) | JsxExpr(depth)) = stack.last_mut() {
+                match kind {
+                    TokenKind::LBrace => *depth += 1,
+                    _ => *depth -= 1,
+                }
+            }
+        }
+        (_, TokenKind::Lt) => {
+            let end = tokens.end[index] as usize;
+            if opts.jsx && jsx_allowed(before, bytes, end) {
+                stack.push(JsxTag {
+                    closing: false,
+                    self_closing: false,
+                });
             }
         }
 
-        state.incr();
-
-        state.add_token(TokenKind::String);
-
-        return Ok(());
+        _ => {}
     }
 }
 
-pub fn lex_template(state: &mut LexState, bytes: &[u8], beginning: bool) {
-    const TICK_MASK: Simd<u8, 32> = Simd::from_array([b'`'; 32]);
-    const LBRACE_MASK: Simd<u8, 32> = Simd::from_array([b'{'; 32]);
-
-    let slash_1_filter = crate::simd::FilterShiftR::<1>::new(b'\\');
-    let dollar_1_filter = crate::simd::FilterShiftR::<1>::new(b'$');
-    let slash_2_filter = crate::simd::FilterShiftR::<2>::new(b'\\');
+/// Lexes the next token, along with any trivia before it that isn't kept as a
+/// token. Returns false at the end of the file.
+pub(crate) fn lex_next<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<bool, LexAbort> {
+    let Some(byte) = state.peek(bytes) else {
+        return Ok(false);
+    };
+
+    // JSX tags and text have their own tokens; everything in their `{}`
+    // gets lexed as usual.
+    if let Some(LexContext::JsxTag { .. } | LexContext::JsxChildren) = state.context_stack.last() {
+        lex_jsx::<S>(state, bytes, symbols)?;
+        return Ok(true);
+    }
 
-    loop {
-        let text = state.peek_32(bytes);
+    state.incr();
 
-        let tick_mask = text.simd_eq(TICK_MASK);
-        let lbrace_mask = text.simd_eq(LBRACE_MASK);
+    // Supposedly LLVM will automatically do the "computed-goto" trick here.
+    // We'll profile/disassemble later ig.
+    match byte {
+        b' ' | b'\t' => lex_whitespace::<S>(state, bytes, false),
+        b'\n' | b'\r' => lex_whitespace::<S>(state, bytes, true),
 
-        let slash_mask_1 = slash_1_filter.check_ne(text);
+        b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'$' | b'\\' | 128..=255 => {
+            lex_word::<S>(state, bytes, symbols)?;
+        }
+        b'.' => {
+            lex_dot_with_suffix(state, bytes, symbols)?;
+        }
+        b'0' => {
+            lex_number_with_prefix(state, bytes, symbols)?;
+        }
+        b'1'..=b'9' => {
+            lex_number(state, bytes, symbols, false)?;
+        }
 
-        let slash_mask_2 = slash_2_filter.check_ne(text);
-        let dollar_mask_1 = dollar_1_filter.check_eq(text);
+        b'\'' => {
+            lex_string::<S>(state, bytes, symbols, StringOpener::Quote)?;
+        }
+        b'"' => {
+            lex_string::<S>(state, bytes, symbols, StringOpener::DubQuote)?;
+        }
+        b'`' => {
+            lex_template::<S>(state, bytes, symbols, true)?;
+        }
 
-        let tick_end_mask = tick_mask & slash_mask_1;
-        let lbrace_end_mask = lbrace_mask & dollar_mask_1 & slash_mask_2;
+        b'/' => {
+            lex_comment_or_div::<S>(state, bytes)?;
+        }
 
-        let index = match (tick_end_mask | lbrace_end_mask).first_set() {
-            Some(i) => i,
-            None => {
-                state.incr_count(30);
-                continue;
-            }
-        };
+        b';' => state.add_token(TokenKind::Semicolon),
+        b':' => state.add_token(TokenKind::Colon),
 
-        state.incr_count(index + 1);
+        b'[' => state.add_token(TokenKind::LBracket),
+        b']' => state.add_token(TokenKind::RBracket),
 
-        let first_tick = tick_end_mask.first_set().unwrap_or(33);
-        let first_lbrace = lbrace_end_mask.first_set().unwrap_or(33);
+        b'(' => state.add_token(TokenKind::LParen),
+        b')' => state.add_token(TokenKind::RParen),
 
-        match (first_tick < first_lbrace, beginning) {
-            (true, true) => state.add_token(TokenKind::StrTemplate),
-            (false, false) => state.add_token(TokenKind::StrTemplateMid),
+        b'{' => {
+            if let Some(LexContext::Template(depth) | LexContext::JsxExpr(depth)) =
+                state.context_stack.last_mut()
+            {
+                *depth += 1;
+            }
 
-            (true, false) => {
-                state.template_level -= 1;
-                state.add_token(TokenKind::StrTemplateEnd);
+            state.add_token(TokenKind::LBrace)
+        }
+        b'}' => match state.context_stack.last_mut() {
+            Some(LexContext::Template(0)) => {
+                state.context_stack.pop();
+                lex_template::<S>(state, bytes, symbols, false)?;
             }
-            (false, true) => {
-                state.template_level += 1;
-                state.add_token(TokenKind::StrTemplateBegin);
+            Some(LexContext::JsxExpr(0)) => {
+                state.context_stack.pop();
+                state.add_token(TokenKind::RBrace);
             }
-        }
+            Some(LexContext::Template(depth) | LexContext::JsxExpr(depth)) => {
+                *depth -= 1;
+                state.add_token(TokenKind::RBrace);
+            }
+            _ => state.add_token(TokenKind::RBrace),
+        },
 
-        // panic!("");
-        break;
-    }
-}
+        b',' => state.add_token(TokenKind::Comma),
+        b'~' => state.add_token(TokenKind::BinNot),
+        b'@' => state.add_token(TokenKind::At),
+        // A `#!` at the very start was already handled by `lex_file_start`.
+        b'#' => {
+            lex_private_name::<S>(state, bytes, symbols)?;
+        }
 
-pub fn lex_dot_with_suffix(state: &mut LexState, bytes: &[u8]) {
-    match state.peek_n::<2>(bytes) {
-        Some([b'.', b'.']) => {
-            state.incr_count(2);
-            state.add_token(TokenKind::Spread);
+        b'+' => state.add_token_longest(
+            bytes,
+            TokenKind::Add,
+            &[(b"+", TokenKind::PlusPlus), (b"=", TokenKind::AddEq)],
+        ),
+        // `-->` is only a comment at the start of a line.
+        b'-' if state.opts.file.allow_html_comments
+            && state.newline_before
+            && state.peek_n::<2>(bytes) == Some(*b"->") =>
+        {
+            lex_line_comment::<S>(state, bytes);
         }
-        Some([b'.', _]) => {
-            state.add_token(TokenKind::Dot);
+        b'-' => state.add_token_longest(
+            bytes,
+            TokenKind::Sub,
+            &[(b"-", TokenKind::MinusMinus), (b"=", TokenKind::SubEq)],
+        ),
+        b'*' => state.add_token_longest(
+            bytes,
+            TokenKind::Mult,
+            &[
+                (b"*=", TokenKind::ExpEq),
+                (b"*", TokenKind::Exp),
+                (b"=", TokenKind::MultEq),
+            ],
+        ),
+        b'%' => state.add_token_longest(bytes, TokenKind::Mod, &[(b"=", TokenKind::ModEq)]),
+        b'^' => state.add_token_longest(bytes, TokenKind::BinXor, &[(b"=", TokenKind::BinXorEq)]),
+
+        b'&' => state.add_token_longest(
+            bytes,
+            TokenKind::BinAnd,
+            &[
+                (b"&=", TokenKind::BoolAndEq),
+                (b"&", TokenKind::BoolAnd),
+                (b"=", TokenKind::BinAndEq),
+            ],
+        ),
+        b'|' => state.add_token_longest(
+            bytes,
+            TokenKind::BinOr,
+            &[
+                (b"|=", TokenKind::BoolOrEq),
+                (b"|", TokenKind::BoolOr),
+                (b"=", TokenKind::BinOrEq),
+            ],
+        ),
+
+        b'=' => state.add_token_longest(
+            bytes,
+            TokenKind::Eq,
+            &[
+                (b"==", TokenKind::EqEqEq),
+                (b"=", TokenKind::EqEq),
+                (b">", TokenKind::Arrow),
+            ],
+        ),
+        b'!' => state.add_token_longest(
+            bytes,
+            TokenKind::Not,
+            &[(b"==", TokenKind::NeqEq), (b"=", TokenKind::Neq)],
+        ),
+
+        b'<' if state.opts.file.allow_html_comments
+            && state.peek_n::<3>(bytes) == Some(*b"!--") =>
+        {
+            lex_line_comment::<S>(state, bytes);
         }
-        Some([b'0'..=b'9', _]) => {
-            lex_number(state, bytes, true);
+        b'<' if state.opts.jsx && state.jsx_allowed(bytes) => {
+            state.context_stack.push(LexContext::JsxTag {
+                closing: false,
+                self_closing: false,
+            });
+            state.add_token(TokenKind::Lt);
         }
-        Some(_) | None => {
-            state.add_token(TokenKind::Dot);
+        b'<' => state.add_token_longest(
+            bytes,
+            TokenKind::Lt,
+            &[
+                (b"<=", TokenKind::ShlEq),
+                (b"<", TokenKind::Shl),
+                (b"=", TokenKind::Leq),
+            ],
+        ),
+        b'>' => state.add_token_longest(
+            bytes,
+            TokenKind::Gt,
+            &[
+                (b">>=", TokenKind::UShrEq),
+                (b">>", TokenKind::UShr),
+                (b">=", TokenKind::ShrEq),
+                (b">", TokenKind::Shr),
+                (b"=", TokenKind::Geq),
+            ],
+        ),
+
+        b'?' => match state.peek_n::<2>(bytes) {
+            // `a?.5:b` is a conditional, not an optional chain.
+            Some([b'.', b'0'..=b'9']) => state.add_token(TokenKind::Question),
+            _ => state.add_token_longest(
+                bytes,
+                TokenKind::Question,
+                &[
+                    (b"?=", TokenKind::NullishEq),
+                    (b"?", TokenKind::Nullish),
+                    (b".", TokenKind::OptChain),
+                ],
+            ),
+        },
+
+        _ => {
+            state.error(
+                LexErrorCode::UnrecognizedCharacter,
+                format!("unrecognized token: {} ({})", char::from(byte), byte),
+            )?;
+
+            state.add_token(TokenKind::Unknown);
         }
     }
+
+    return Ok(true);
 }
 
-pub fn lex_number_with_prefix(state: &mut LexState, bytes: &[u8]) {
-    match state.peek(bytes).unwrap_or(0) {
-        b'n' => state.add_token(TokenKind::BigInt),
-        b'e' => return lex_number(state, bytes, true),
-        b'.' => {
-            state.incr();
-            return lex_number(state, bytes, true);
-        }
+// Reports anything that's still open at the end of the file. Templates report
+// that themselves.
+pub(crate) fn lex_file_end(state: &mut LexState) -> Result<(), LexAbort> {
+    let in_jsx = |context: &LexContext| !matches!(context, LexContext::Template(_));
+    if state.context_stack.iter().any(in_jsx) {
+        state.error(
+            LexErrorCode::UnterminatedJsx,
+            format!("File ended without finishing JSX element"),
+        )?;
+    }
 
-        b'b' | b'B' => {
-            state.incr();
-            unimplemented!();
-        }
-        b'x' | b'X' => {
-            state.incr();
-            unimplemented!();
-        }
-        b'o' | b'O' => {
-            state.incr();
-            unimplemented!();
-        }
+    return Ok(());
+}
 
-        b'0'..=b'9' => {
-            unimplemented!();
-        }
+const BOM: &[u8] = "\u{feff}".as_bytes();
 
-        _ => {
-            state.add_token(TokenKind::Number);
-        }
+// A byte order mark and a hashbang only mean anything at the very start.
+pub(crate) fn lex_file_start<S: Scanner>(state: &mut LexState, bytes: &[u8]) {
+    if state.opts.file.allow_bom && bytes.starts_with(BOM) {
+        state.index = BOM.len();
+        state.begin_index = state.index;
+    }
+
+    if state.opts.file.allow_hashbang && bytes[state.index..].starts_with(b"#!") {
+        state.index = S::find_line_break(bytes, state.index);
+        state.add_token_cond(TokenKind::Hashbang, state.opts.include_comments);
     }
 }
 
-pub fn lex_number(state: &mut LexState, bytes: &[u8], mut has_dot: bool) {
-    // TODO: SIMD-ify?
-    let mut has_exponent = false;
+// The newline itself isn't part of the comment.
+fn lex_line_comment<S: Scanner>(state: &mut LexState, bytes: &[u8]) {
+    state.index = S::find_line_break(bytes, state.index);
+    state.add_token_cond(TokenKind::LineComment, state.opts.include_comments);
+}
 
-    loop {
-        match state.peek(bytes).unwrap_or(0) {
-            b'0'..=b'9' => {
-                // its definitely a number
+#[repr(u8)]
+pub enum StringOpener {
+    Quote = b'\'',
+    DubQuote = b'"',
+}
 
-                state.incr();
-            }
+pub fn lex_comment_or_div<S: Scanner>(state: &mut LexState, bytes: &[u8]) -> Result<(), LexAbort> {
+    match state.peek(bytes).unwrap_or(0) {
+        b'/' => lex_line_comment::<S>(state, bytes),
 
-            b'.' => {
-                if has_exponent {
-                    state.add_token(TokenKind::Number);
-                    return;
-                }
+        b'*' => {
+            state.incr();
 
-                if has_dot {
-                    state.add_token(TokenKind::Number);
-                    return;
+            // For ASI, a comment with a line break in it counts as a line break.
+            let (end, has_newline) = S::find_comment_end(bytes, state.index);
+            state.newline_before = state.newline_before || has_newline;
+
+            match end {
+                Some(end) => state.index = end,
+                None => {
+                    state.index = bytes.len();
+                    state.error(
+                        LexErrorCode::UnterminatedComment,
+                        format!("File ended without finishing comment"),
+                    )?;
                 }
-
-                has_dot = true;
-                state.incr();
             }
 
-            b'e' => {
-                if has_exponent {
-                    unimplemented!();
-                }
+            state.add_token_cond(TokenKind::Comment, state.opts.include_comments);
+        }
 
-                has_exponent = true;
-            }
+        _ if state.regex_allowed() => return lex_regex(state, bytes),
 
-            _ => {
-                state.add_token(TokenKind::Number);
-                return;
-            }
+        b'=' => {
+            state.incr();
+            state.add_token(TokenKind::DivEq);
+        }
+
+        _ => {
+            state.add_token(TokenKind::Div);
         }
     }
+
+    return Ok(());
 }
 
-// TODO: handle utf-8 characters
-pub fn lex_word(state: &mut LexState, bytes: &[u8], symbols: &mut Symbols) {
-    const EQ_0: Simd<u8, 32> = Simd::from_array([0u8; 32]);
+// Expects the opening slash to already be consumed.
+pub fn lex_regex(state: &mut LexState, bytes: &[u8]) -> Result<(), LexAbort> {
+    let mut in_class = false;
 
     loop {
-        let text = Simd::from_array(state.peek_32(bytes).to_array().map(|a| a as usize));
-        let word_mask = Simd::gather_or_default(&*ALNUM_MAP, text);
-        let non_alnum_mask = word_mask.simd_eq(EQ_0);
+        if let None | Some(b'\n' | b'\r') = state.peek(bytes) {
+            state.error(
+                LexErrorCode::UnterminatedRegex,
+                format!(
+                    "unterminated regular expression starting at byte {}",
+                    state.begin_index
+                ),
+            )?;
+
+            state.add_token(TokenKind::Regex);
+            return Ok(());
+        }
+
+        match state.pop(bytes) {
+            Some(b'\\') => match state.peek(bytes) {
+                None | Some(b'\n' | b'\r') => {}
+                Some(_) => state.incr(),
+            },
+
+            Some(b'[') => in_class = true,
+            Some(b']') => in_class = false,
+            Some(b'/') if !in_class => break,
+
+            _ => {}
+        }
+    }
 
-        if let Some(index) = non_alnum_mask.first_set() {
-            state.incr_count(index);
+    // Flags; which ones are valid is the parser's problem.
+    while let Some(c) = state.peek(bytes) {
+        if ALNUM_MAP[c as usize] == 0 {
             break;
         }
 
-        state.incr_count(32);
+        state.incr();
     }
 
-    let word = state.text(bytes);
+    state.add_token(TokenKind::Regex);
+
+    return Ok(());
+}
 
-    if let Some(key) = KEY_HASH_MAP.get(word) {
-        state.add_token(TokenKind::Key(*key));
-        return;
+pub fn lex_string<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+    opener: StringOpener,
+) -> Result<(), LexAbort> {
+    let quote = opener as u8;
+    state.index = S::find_string_end(bytes, state.index, quote);
+
+    let content = state.begin_index + 1..state.index;
+
+    // When recovering, the string just ends before the newline.
+    match state.peek(bytes) {
+        Some(c) if c == quote => state.incr(),
+        Some(_) => state.error(
+            LexErrorCode::NewlineInString,
+            format!("String ended with newline instead of quote"),
+        )?,
+        None => state.error(
+            LexErrorCode::UnterminatedString,
+            format!("File ended without finishing string"),
+        )?,
     }
 
-    // TODO: we can get rid of this unsafe if desired. Whatever.
-    let symbol = symbols.add_str(unsafe { core::str::from_utf8_unchecked(word) });
+    let id = cook_string_token(state, bytes, symbols, content)?;
+    state.add_token_extra(TokenKind::String, id);
 
-    state.add_token_extra(TokenKind::Word, symbol);
+    return Ok(());
 }
 
-pub fn lex_whitespace(state: &mut LexState, bytes: &[u8], mut has_newline: bool) {
-    const TAB: Simd<u8, 32> = Simd::from_array([b'\t'; 32]);
-    const SPACE: Simd<u8, 32> = Simd::from_array([b' '; 32]);
-    const CARRIAGE_RETURN: Simd<u8, 32> = Simd::from_array([b'\r'; 32]);
+pub fn lex_template<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+    beginning: bool,
+) -> Result<(), LexAbort> {
+    state.index = S::find_template_end(bytes, state.index);
+
+    let content_start = state.begin_index + 1;
+    let Some(end) = state.peek(bytes) else {
+        state.error(
+            LexErrorCode::UnterminatedTemplate,
+            format!("File ended without finishing template"),
+        )?;
+
+        let id = cook_template_token(bytes, symbols, content_start..state.index);
+        match beginning {
+            true => state.add_token_extra(TokenKind::StrTemplate, id),
+            false => state.add_token_extra(TokenKind::StrTemplateEnd, id),
+        }
 
-    loop {
-        let bytes = state.peek_32(bytes);
+        return Ok(());
+    };
 
-        let newlines = bytes.simd_eq(NEWLINE_SIMD);
-        let tabs = bytes.simd_eq(TAB);
-        let spaces = bytes.simd_eq(SPACE);
-        let carriage_returns = bytes.simd_eq(CARRIAGE_RETURN);
+    // The content stops before the closing tick, or before the `${`.
+    let is_tick = end == b'`';
+    let content_end = state.index - !is_tick as usize;
+    let id = cook_template_token(bytes, symbols, content_start..content_end);
 
-        let newline_mask = newlines | carriage_returns;
-        let whitespace_mask = newline_mask | spaces | tabs;
-        let whitespace_mask = !whitespace_mask;
-        has_newline = has_newline || newline_mask.any();
+    state.incr();
 
-        if let Some(index) = whitespace_mask.first_set() {
-            state.incr_count(index);
+    match (is_tick, beginning) {
+        (true, true) => state.add_token_extra(TokenKind::StrTemplate, id),
+        (true, false) => state.add_token_extra(TokenKind::StrTemplateEnd, id),
+        (false, false) => {
+            state.context_stack.push(LexContext::Template(0));
+            state.add_token_extra(TokenKind::StrTemplateMid, id);
+        }
+        (false, true) => {
+            state.context_stack.push(LexContext::Template(0));
+            state.add_token_extra(TokenKind::StrTemplateBegin, id);
+        }
+    }
 
-            state.add_token_cond(
-                TokenKind::Whitespace,
-                state.opts.include_spacing || has_newline,
-            );
+    return Ok(());
+}
 
-            break;
-        }
+// Returns the string's id in `Symbols::strings`, or 0 if it has a bad escape.
+fn cook_string_token(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+    content: Range<usize>,
+) -> Result<u32, LexAbort> {
+    let raw = &bytes[content.clone()];
+    if !raw.contains(&b'\\') {
+        return Ok(symbols.strings.add(raw));
+    }
 
-        state.incr_count(32);
+    let raw = core::str::from_utf8(raw).expect("literal delimiters are ASCII");
+    let mut cooked = Vec::with_capacity(raw.len());
+    if let Err(e) = cook_string(raw, false, &mut cooked) {
+        let span = content.start + e.start..content.start + e.end;
+        state.error_at(LexErrorCode::InvalidEscape, span, e.message)?;
+        return Ok(0);
     }
+
+    return Ok(symbols.strings.add(&cooked));
 }
 
-type MyFunc = for<'a> fn(state: &'a mut LexState, bytes: &'a [u8]);
+// Bad escapes are only errors in untagged templates, which the lexer can't
+// tell apart, so they just leave the piece without a cooked value.
+fn cook_template_token(bytes: &[u8], symbols: &mut Symbols, content: Range<usize>) -> u32 {
+    let raw = &bytes[content];
+    if !raw.iter().any(|&c| c == b'\\' || c == b'\r') {
+        return symbols.strings.add_template(Some(raw), raw);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::util::tests::*;
+    let raw = core::str::from_utf8(raw).expect("literal delimiters are ASCII");
+    let mut raw_value = Vec::with_capacity(raw.len());
+    template_raw(raw, &mut raw_value);
 
-    #[test_resources("test/easy/*")]
-    fn lex_easy(path: &str) {
-        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+    let mut cooked = Vec::with_capacity(raw.len());
+    let cooked = match cook_string(raw, true, &mut cooked) {
+        Ok(()) => Some(&cooked[..]),
+        Err(_) => None,
+    };
 
-        let mut symbols = Symbols::new();
-        let tokens = lex(&source, &mut symbols)
-            .map_err(|e| e.error)
-            .expect("doesn't error");
+    return symbols.strings.add_template(cooked, &raw_value);
+}
+
+pub fn lex_dot_with_suffix(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<(), LexAbort> {
+    match state.peek_n::<2>(bytes) {
+        Some([b'.', b'.']) => {
+            state.incr_count(2);
+            state.add_token(TokenKind::Spread);
+        }
+        _ => match state.peek(bytes) {
+            Some(b'0'..=b'9') => return lex_number(state, bytes, symbols, true),
+            _ => state.add_token(TokenKind::Dot),
+        },
+    }
+
+    return Ok(());
+}
+
+pub fn lex_number_with_prefix(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<(), LexAbort> {
+    let result = scan_number_with_prefix(state, bytes);
+    return finish_number(state, bytes, symbols, result);
+}
+
+pub fn lex_number(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+    has_dot: bool,
+) -> Result<(), LexAbort> {
+    let result = scan_number(state, bytes, has_dot);
+    return finish_number(state, bytes, symbols, result);
+}
+
+// The token's `extra` is its id in `Symbols::numbers`, or 0 if it's invalid.
+fn finish_number(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+    result: Result<TokenKind, String>,
+) -> Result<(), LexAbort> {
+    let message = match result {
+        Ok(kind) => {
+            let value = parse_number(state.text(bytes));
+            let id = symbols.numbers.add(value);
+            state.add_token_extra(kind, id);
+            return Ok(());
+        }
+        Err(message) => message,
+    };
+
+    // The rest of the alphanumeric run is part of the bad literal.
+    while let Some(c) = state.peek(bytes) {
+        if ALNUM_MAP[c as usize] == 0 {
+            break;
+        }
+
+        state.incr();
+    }
+
+    state.error(LexErrorCode::InvalidNumber, message)?;
+    state.add_token(TokenKind::Number);
+
+    return Ok(());
+}
+
+fn scan_number_with_prefix(state: &mut LexState, bytes: &[u8]) -> Result<TokenKind, String> {
+    let (kind, is_digit): (TokenKind, fn(u8) -> bool) = match state.peek(bytes).unwrap_or(0) {
+        b'b' | b'B' => (TokenKind::BinNumber, |c| matches!(c, b'0'..=b'1')),
+        b'o' | b'O' => (TokenKind::OctNumber, |c| matches!(c, b'0'..=b'7')),
+        b'x' | b'X' => (TokenKind::HexNumber, |c| c.is_ascii_hexdigit()),
+
+        b'0'..=b'9' => return scan_legacy_octal(state, bytes),
+
+        b'_' => return Err(format!("numeric separator can't follow a leading 0")),
+
+        // `0`, `0.5`, `0e1` and `0n` all look like a normal decimal from here.
+        _ => return scan_number(state, bytes, false),
+    };
+
+    state.incr();
+
+    if lex_digits(state, bytes, is_digit, false)? == 0 {
+        return Err(format!("numeric literal has a radix prefix but no digits"));
+    }
+
+    let kind = match state.peek(bytes) {
+        Some(b'n') => {
+            state.incr();
+            TokenKind::BigInt
+        }
+        _ => kind,
+    };
+
+    check_number_end(state, bytes)?;
+
+    return Ok(kind);
+}
+
+// Annex B: `017` is octal, and `019` is a decimal that happens to start with 0.
+// Neither allows separators, a BigInt suffix, or (for the octal) a fraction.
+fn scan_legacy_octal(state: &mut LexState, bytes: &[u8]) -> Result<TokenKind, String> {
+    let mut is_octal = true;
+    while let Some(c @ b'0'..=b'9') = state.peek(bytes) {
+        is_octal = is_octal && c <= b'7';
+        state.incr();
+    }
+
+    match state.peek(bytes) {
+        Some(b'_') => {
+            return Err(format!(
+                "numeric separators aren't allowed in legacy octal literals"
+            ))
+        }
+        Some(b'n') => return Err(format!("legacy octal literals can't be BigInts")),
+        _ => {}
+    }
+
+    if !is_octal {
+        return scan_number(state, bytes, false);
+    }
+
+    check_number_end(state, bytes)?;
+
+    return Ok(TokenKind::OctNumber);
+}
+
+fn scan_number(state: &mut LexState, bytes: &[u8], mut has_dot: bool) -> Result<TokenKind, String> {
+    // TODO: SIMD-ify?
+    let is_digit: fn(u8) -> bool = |c| c.is_ascii_digit();
+
+    // Either the first digit was already popped, or we're right after the dot
+    // in `.5`, in which case the digit is still ahead of us.
+    lex_digits(state, bytes, is_digit, !has_dot)?;
+
+    if !has_dot && state.peek(bytes) == Some(b'.') {
+        has_dot = true;
+        state.incr();
+
+        lex_digits(state, bytes, is_digit, false)?;
+    }
+
+    let mut has_exponent = false;
+    if let Some(b'e' | b'E') = state.peek(bytes) {
+        has_exponent = true;
+        state.incr();
+
+        if let Some(b'+' | b'-') = state.peek(bytes) {
+            state.incr();
+        }
+
+        if lex_digits(state, bytes, is_digit, false)? == 0 {
+            return Err(format!("numeric literal has an exponent but no digits"));
+        }
+    }
+
+    let mut kind = TokenKind::Number;
+    if state.peek(bytes) == Some(b'n') {
+        if has_dot || has_exponent {
+            return Err(format!("BigInt literals must be integers"));
+        }
+
+        state.incr();
+        kind = TokenKind::BigInt;
+    }
+
+    check_number_end(state, bytes)?;
+
+    return Ok(kind);
+}
+
+/// Consumes digits with `_` separators between them, and returns the number
+/// of digits consumed. `after_digit` is whether the previous byte was a digit,
+/// i.e. whether a separator is allowed right away.
+fn lex_digits(
+    state: &mut LexState,
+    bytes: &[u8],
+    is_digit: fn(u8) -> bool,
+    mut after_digit: bool,
+) -> Result<usize, String> {
+    let mut count = 0;
+
+    loop {
+        let c = state.peek(bytes).unwrap_or(0);
+        if is_digit(c) {
+            count += 1;
+            after_digit = true;
+            state.incr();
+            continue;
+        }
+
+        if c != b'_' {
+            return Ok(count);
+        }
+
+        state.incr();
+
+        if !after_digit || !is_digit(state.peek(bytes).unwrap_or(0)) {
+            return Err(format!(
+                "numeric separators are only allowed between digits"
+            ));
+        }
+
+        after_digit = false;
+    }
+}
+
+// The spec doesn't allow an identifier or digit directly after a numeric
+// literal, so `3in x` and `0b12` are errors instead of two tokens.
+fn check_number_end(state: &LexState, bytes: &[u8]) -> Result<(), String> {
+    let c = state.peek(bytes).unwrap_or(0);
+    if ALNUM_MAP[c as usize] != 0 {
+        return Err(format!(
+            "unexpected character after numeric literal: {}",
+            char::from(c)
+        ));
+    }
+
+    return Ok(());
+}
+
+pub fn lex_word<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<(), LexAbort> {
+    let mut has_escape = false;
+
+    // ASCII starts were already checked by the dispatch in `lex_with_options`.
+    let first = bytes[state.begin_index];
+    if first == b'\\' || !first.is_ascii() {
+        state.index = state.begin_index;
+        has_escape = first == b'\\';
+
+        match lex_ident_char(state, bytes, true) {
+            Ok(true) => {}
+            Ok(false) => {
+                let (c, len) = decode_utf8(&bytes[state.index..]).unwrap_or(('\0', 1));
+                state.incr_count(len);
+                state.error(
+                    LexErrorCode::UnrecognizedCharacter,
+                    format!("unexpected character: {} ({:#x})", c, c as u32),
+                )?;
+
+                state.add_token(TokenKind::Unknown);
+                return Ok(());
+            }
+            Err(message) => return invalid_word(state, bytes, message),
+        }
+    }
+
+    match lex_ident_rest::<S>(state, bytes) {
+        Ok(escaped) => has_escape = has_escape || escaped,
+        Err(message) => return invalid_word(state, bytes, message),
+    }
+
+    let word = state.text(bytes);
+
+    // Escaped keywords like `if` are never keywords; whether they're
+    // allowed as identifiers is up to the parser.
+    if has_escape {
+        let name = match cook_ident(word) {
+            Ok(name) => name,
+            Err(message) => return invalid_word(state, bytes, message),
+        };
+
+        let symbol = symbols.add_str(name);
+        state.add_token_extra(TokenKind::Word, symbol);
+        return Ok(());
+    }
+
+    match lookup_keyword(word) {
+        Some(Keyword::Key(key)) => {
+            state.add_token(TokenKind::Key(key));
+            return Ok(());
+        }
+
+        // Already interned by `Symbols::new`.
+        Some(Keyword::Ctx(key)) => {
+            state.add_token_extra(TokenKind::Word, key.symbol());
+            return Ok(());
+        }
+
+        None => {}
+    }
+
+    let word = match core::str::from_utf8(word) {
+        Ok(word) => word,
+        Err(e) => return invalid_word(state, bytes, format!("{}", e)),
+    };
+    let symbol = symbols.add_str(word);
+
+    state.add_token_extra(TokenKind::Word, symbol);
+
+    return Ok(());
+}
+
+/// Lexes `#name`, which can only be a private class member. Whether it's
+/// actually inside a class body is up to the parser. A `#` that isn't followed
+/// by an identifier is a lone `Hash` token.
+fn lex_private_name<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<(), LexAbort> {
+    let has_escape = bytes.get(state.index) == Some(&b'\\');
+    match lex_ident_char(state, bytes, true) {
+        Ok(true) => {}
+        Ok(false) => {
+            state.add_token(TokenKind::Hash);
+            return Ok(());
+        }
+        Err(message) => return invalid_word(state, bytes, message),
+    }
+
+    let has_escape = match lex_ident_rest::<S>(state, bytes) {
+        Ok(escaped) => has_escape || escaped,
+        Err(message) => return invalid_word(state, bytes, message),
+    };
+
+    // The `#` stays in the interned name, so `#a` and `a` get different
+    // symbols. Keywords are fine here, e.g. `#if`.
+    let raw = &state.text(bytes)[1..];
+    let name = if has_escape {
+        cook_ident(raw)
+    } else {
+        core::str::from_utf8(raw)
+            .map(String::from)
+            .map_err(|e| format!("{}", e))
+    };
+    let name = match name {
+        Ok(name) => name,
+        Err(message) => return invalid_word(state, bytes, message),
+    };
+
+    let symbol = symbols.add_str(format!("#{}", name));
+    state.add_token_extra(TokenKind::PrivateName, symbol);
+
+    return Ok(());
+}
+
+/// Consumes identifier characters after the first one, and returns whether
+/// any of them were escapes.
+fn lex_ident_rest<S: Scanner>(state: &mut LexState, bytes: &[u8]) -> Result<bool, String> {
+    let mut has_escape = false;
+    loop {
+        // Fast path for ASCII identifier characters, which `ALNUM_MAP` marks
+        // with a 1. Escapes and non-ASCII code points get validated below.
+        state.index = S::skip_ascii_ident(bytes, state.index);
+
+        match state.peek(bytes) {
+            Some(c @ (b'\\' | 128..=255)) => match lex_ident_char(state, bytes, false)? {
+                true => has_escape = has_escape || c == b'\\',
+                false => break,
+            },
+            _ => break,
+        }
+    }
+
+    return Ok(has_escape);
+}
+
+// When recovering, the rest of the word (escapes included) becomes one
+// `Unknown` token.
+fn invalid_word(state: &mut LexState, bytes: &[u8], message: String) -> Result<(), LexAbort> {
+    while let Some(c) = state.peek(bytes) {
+        if ALNUM_MAP[c as usize] == 0 && c != b'\\' {
+            break;
+        }
+
+        state.incr();
+    }
+
+    state.error(LexErrorCode::InvalidIdentifier, message)?;
+    state.add_token(TokenKind::Unknown);
+
+    return Ok(());
+}
+
+/// Consumes one identifier code point, which is either a literal character or
+/// a `\u` escape. Returns false if the next character can't be part of an
+/// identifier. Escapes that decode to a bad code point are always errors.
+fn lex_ident_char(state: &mut LexState, bytes: &[u8], start: bool) -> Result<bool, String> {
+    let rest = &bytes[state.index..];
+    let len = match rest.first() {
+        None => return Ok(false),
+        Some(b'\\') => {
+            let (c, len) = parse_unicode_escape(rest)?;
+            if !is_ident_char(c, start) {
+                return Err(format!(
+                    "escape sequence {:?} isn't valid in an identifier",
+                    core::str::from_utf8(&rest[..len]).unwrap_or("")
+                ));
+            }
+
+            len
+        }
+        Some(_) => match decode_utf8(rest) {
+            Some((c, len)) if is_ident_char(c, start) => len,
+            _ => return Ok(false),
+        },
+    };
+
+    state.incr_count(len);
+
+    return Ok(true);
+}
+
+pub fn is_ident_char(c: char, start: bool) -> bool {
+    if c == '$' || c == '_' {
+        return true;
+    }
+
+    if start {
+        return unicode_ident::is_xid_start(c);
+    }
+
+    // ZWNJ and ZWJ
+    return unicode_ident::is_xid_continue(c) || c == '\u{200c}' || c == '\u{200d}';
+}
+
+/// Parses `\uXXXX` or `\u{X...}` at the start of `bytes`, returning the code
+/// point and the length of the escape.
+pub fn parse_unicode_escape(bytes: &[u8]) -> Result<(char, usize), String> {
+    let invalid = || format!("invalid unicode escape sequence");
+
+    let Some(b"\\u") = bytes.get(..2) else {
+        return Err(invalid());
+    };
+
+    let (digits, len) = match bytes.get(2) {
+        // Only the hex digits are searched for the `}`, so an unclosed `\u{`
+        // doesn't scan the rest of the file.
+        Some(b'{') => {
+            let close = 3 + bytes[3..]
+                .iter()
+                .take_while(|c| c.is_ascii_hexdigit())
+                .count();
+            if bytes.get(close) != Some(&b'}') {
+                return Err(invalid());
+            }
+
+            (&bytes[3..close], close + 1)
+        }
+        _ => (bytes.get(2..6).ok_or_else(invalid)?, 6),
+    };
+
+    if digits.is_empty() || !digits.iter().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let digits = core::str::from_utf8(digits).map_err(|_| invalid())?;
+    let value = u32::from_str_radix(digits, 16).map_err(|_| invalid())?;
+    let c = char::from_u32(value).ok_or_else(invalid)?;
+
+    return Ok((c, len));
+}
+
+// Decodes an identifier that's already been validated by `lex_ident_char`.
+fn cook_ident(raw: &[u8]) -> Result<String, String> {
+    let mut name = String::with_capacity(raw.len());
+    let mut index = 0;
+
+    while index < raw.len() {
+        let (c, len) = match raw[index] {
+            b'\\' => parse_unicode_escape(&raw[index..])?,
+            _ => decode_utf8(&raw[index..]).ok_or_else(|| format!("invalid UTF-8"))?,
+        };
+
+        name.push(c);
+        index += len;
+    }
+
+    return Ok(name);
+}
+
+fn decode_utf8(bytes: &[u8]) -> Option<(char, usize)> {
+    let len = match *bytes.first()? {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+
+    let c = core::str::from_utf8(bytes.get(..len)?)
+        .ok()?
+        .chars()
+        .next()?;
+
+    return Some((c, len));
+}
+
+// Lexes one token of a JSX tag, or of the children between tags.
+fn lex_jsx<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<(), LexAbort> {
+    if state.context_stack.last() == Some(&LexContext::JsxChildren) {
+        lex_jsx_children(state, bytes, symbols);
+        return Ok(());
+    }
+
+    let Some(byte) = state.pop(bytes) else {
+        return Ok(());
+    };
+
+    match byte {
+        b' ' | b'\t' => lex_whitespace::<S>(state, bytes, false),
+        b'\n' | b'\r' => lex_whitespace::<S>(state, bytes, true),
+
+        b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'$' | 128..=255 => {
+            return lex_jsx_ident::<S>(state, bytes, symbols);
+        }
+        b'"' | b'\'' => return lex_jsx_string(state, bytes, symbols, byte),
+
+        b'/' if matches!(state.peek(bytes), Some(b'/' | b'*')) => {
+            return lex_comment_or_div::<S>(state, bytes);
+        }
+        b'/' => {
+            // Right after the `<` it's `</a>`, otherwise it's `<a />`.
+            let kinds = state.tokens.kind.iter().rev();
+            let after_lt = kinds.copied().find(|kind| !kind.is_trivia()) == Some(TokenKind::Lt);
+            if let Some(LexContext::JsxTag {
+                closing,
+                self_closing,
+            }) = state.context_stack.last_mut()
+            {
+                *closing = *closing || after_lt;
+                *self_closing = *self_closing || !after_lt;
+            }
+
+            state.add_token(TokenKind::Div);
+        }
+        b'>' => {
+            state.add_token(TokenKind::Gt);
+
+            match state.context_stack.pop() {
+                // Closing tags only come up in children, which are done too.
+                Some(LexContext::JsxTag { closing: true, .. }) => {
+                    let children = state.context_stack.pop();
+                    debug_assert_eq!(children, Some(LexContext::JsxChildren));
+                }
+                Some(LexContext::JsxTag {
+                    self_closing: false,
+                    ..
+                }) => state.context_stack.push(LexContext::JsxChildren),
+                _ => {}
+            }
+        }
+
+        b'{' => {
+            state.context_stack.push(LexContext::JsxExpr(0));
+            state.add_token(TokenKind::LBrace);
+        }
+        b'=' => state.add_token(TokenKind::Eq),
+        b':' => state.add_token(TokenKind::Colon),
+        b'.' => state.add_token(TokenKind::Dot),
+
+        _ => {
+            state.error(
+                LexErrorCode::UnrecognizedCharacter,
+                format!("unexpected character in JSX tag: {}", char::from(byte)),
+            )?;
+
+            state.add_token(TokenKind::Unknown);
+        }
+    }
+
+    return Ok(());
+}
+
+// Text runs until the next tag or `{`. Whitespace is part of the text; what to
+// do with it is up to whoever uses the tokens.
+fn lex_jsx_children(state: &mut LexState, bytes: &[u8], symbols: &mut Symbols) {
+    match state.pop(bytes) {
+        Some(b'<') => {
+            state.context_stack.push(LexContext::JsxTag {
+                closing: false,
+                self_closing: false,
+            });
+            state.add_token(TokenKind::Lt);
+        }
+        Some(b'{') => {
+            state.context_stack.push(LexContext::JsxExpr(0));
+            state.add_token(TokenKind::LBrace);
+        }
+        _ => {
+            let rest = &bytes[state.begin_index..];
+            let len = rest.iter().position(|&c| c == b'<' || c == b'{');
+            state.index = state.begin_index + len.unwrap_or(rest.len());
+
+            let id = cook_jsx_text(bytes, symbols, state.span());
+            state.add_token_extra(TokenKind::JsxText, id);
+        }
+    }
+}
+
+// JSX names can have `-` in them, like `aria-label`, and are never keywords.
+fn lex_jsx_ident<S: Scanner>(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+) -> Result<(), LexAbort> {
+    state.index = state.begin_index;
+    if lex_ident_char(state, bytes, true) != Ok(true) {
+        let (c, len) = decode_utf8(&bytes[state.index..]).unwrap_or(('\0', 1));
+        state.incr_count(len);
+        state.error(
+            LexErrorCode::UnrecognizedCharacter,
+            format!("unexpected character: {} ({:#x})", c, c as u32),
+        )?;
+
+        state.add_token(TokenKind::Unknown);
+        return Ok(());
+    }
+
+    loop {
+        state.index = S::skip_ascii_ident(bytes, state.index);
+
+        match state.peek(bytes) {
+            Some(b'-') => state.incr(),
+            Some(128..=255) if lex_ident_char(state, bytes, false) == Ok(true) => {}
+            _ => break,
+        }
+    }
+
+    let name = match core::str::from_utf8(state.text(bytes)) {
+        Ok(name) => name,
+        Err(e) => return invalid_word(state, bytes, format!("{}", e)),
+    };
+    let symbol = symbols.add_str(name);
+    state.add_token_extra(TokenKind::JsxIdent, symbol);
+
+    return Ok(());
+}
+
+// Attribute strings can span lines, and have no escapes.
+fn lex_jsx_string(
+    state: &mut LexState,
+    bytes: &[u8],
+    symbols: &mut Symbols,
+    quote: u8,
+) -> Result<(), LexAbort> {
+    let rest = &bytes[state.index..];
+    let content = match rest.iter().position(|&c| c == quote) {
+        Some(len) => {
+            state.incr_count(len + 1);
+            state.begin_index + 1..state.index - 1
+        }
+        None => {
+            state.index = bytes.len();
+            state.error(
+                LexErrorCode::UnterminatedString,
+                format!("File ended without finishing string"),
+            )?;
+
+            state.begin_index + 1..state.index
+        }
+    };
+
+    let id = cook_jsx_text(bytes, symbols, content);
+    state.add_token_extra(TokenKind::JsxString, id);
+
+    return Ok(());
+}
+
+// Returns the id in `Symbols::strings`, with character references decoded.
+fn cook_jsx_text(bytes: &[u8], symbols: &mut Symbols, content: Range<usize>) -> u32 {
+    let raw = &bytes[content];
+    if !raw.contains(&b'&') {
+        return symbols.strings.add(raw);
+    }
+
+    let mut cooked = Vec::with_capacity(raw.len());
+    decode_jsx_entities(raw, &mut cooked);
+    return symbols.strings.add(&cooked);
+}
+
+pub fn lex_whitespace<S: Scanner>(state: &mut LexState, bytes: &[u8], has_newline: bool) {
+    let (end, more_newlines) = S::skip_whitespace(bytes, state.index);
+    state.index = end;
+
+    state.newline_before = state.newline_before || has_newline || more_newlines;
+    state.add_token_cond(TokenKind::Whitespace, state.opts.include_spacing);
+}
+
+type MyFunc = for<'a> fn(state: &'a mut LexState, bytes: &'a [u8]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::*;
+
+    #[test_resources("test/easy/*")]
+    fn lex_easy(path: &str) {
+        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+
+        let opts = LexOptions {
+            jsx: path.ends_with(".tsx"),
+            ..Default::default()
+        };
+
+        let mut symbols = Symbols::new();
+        let tokens = lex_with_options(&source, &mut symbols, opts)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
 
         println!("{}", source);
 
@@ -568,4 +1813,812 @@ mod tests {
 
         assert_eq!(&output, &expected_tokens);
     }
+
+    // Both backends have to agree on everything, errors included. Padding the
+    // start moves things across chunk boundaries.
+    #[cfg(feature = "simd")]
+    #[test_resources("test/**/*.ts")]
+    fn lex_backends_agree(path: &str) {
+        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+        check_backends_agree(&source);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn lex_backends_agree_on_errors() {
+        let sources = [
+            "'abc\\\r\ndef\\\\' \"x\\\"",
+            "'abc\ndef'",
+            "`a\\${b}$${c}\\\\${d}`",
+            "`abc",
+            "/* a\n*",
+            "/* a **/ b // c\r\nd",
+            "x\t\r\n  \u{2028}",
+        ];
+
+        for source in sources {
+            check_backends_agree(source);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn check_backends_agree(source: &str) {
+        use crate::scan::ScalarScanner;
+        use crate::simd::{SimdLanes, SimdScanner};
+
+        fn lex_all<S: Scanner>(source: &str) -> (Vec<Token>, Vec<LexDiagnostic>) {
+            let opts = LexOptions {
+                include_comments: true,
+                include_spacing: true,
+                recover: true,
+                ..Default::default()
+            };
+
+            let mut symbols = Symbols::new();
+            let (tokens, diagnostics) = match lex_with_scanner::<S>(source, &mut symbols, opts) {
+                Ok(tokens) => (tokens, Vec::new()),
+                Err(e) => (e.tokens, e.diagnostics),
+            };
+
+            let tokens: Vec<Token> = tokens.iter().map(|token| token.to_owned()).collect();
+            return (tokens, diagnostics);
+        }
+
+        for padding in 0..64 {
+            let source = format!("{}{}", " ".repeat(padding), source);
+            let expected = lex_all::<ScalarScanner>(&source);
+
+            assert_eq!(lex_all::<SimdScanner>(&source), expected, "{}", padding);
+            assert_eq!(lex_all::<SimdLanes<16>>(&source), expected, "{}", padding);
+            assert_eq!(lex_all::<SimdLanes<32>>(&source), expected, "{}", padding);
+            assert_eq!(lex_all::<SimdLanes<64>>(&source), expected, "{}", padding);
+        }
+    }
+
+    // Generated bundles are mostly long strings and comments.
+    #[cfg(feature = "simd")]
+    fn bench_long_literals<S: Scanner>(bencher: &mut Bencher) {
+        let line = format!("/* {} */ x = '{}';\n", "c".repeat(500), "s\\'".repeat(200));
+        let source = line.repeat(100);
+
+        bencher.iter(|| {
+            let mut symbols = Symbols::new();
+            let result =
+                lex_with_scanner::<S>(black_box(&source), &mut symbols, Default::default());
+            black_box(result.map_err(|e| e.diagnostics).expect("doesn't error"));
+        });
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn lex_long_literals_16_bench(bencher: &mut Bencher) {
+        bench_long_literals::<crate::simd::SimdLanes<16>>(bencher);
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn lex_long_literals_32_bench(bencher: &mut Bencher) {
+        bench_long_literals::<crate::simd::SimdLanes<32>>(bencher);
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn lex_long_literals_64_bench(bencher: &mut Bencher) {
+        bench_long_literals::<crate::simd::SimdLanes<64>>(bencher);
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn lex_long_literals_dispatch_bench(bencher: &mut Bencher) {
+        bench_long_literals::<crate::simd::SimdScanner>(bencher);
+    }
+
+    #[test]
+    fn lex_malformed_numbers() {
+        for source in [
+            "0x", "0b2", "1__0", "1_", "0_1", "1e", "1e+", "1.5n", "017n", "3in",
+        ] {
+            let mut symbols = Symbols::new();
+            assert!(lex(source, &mut symbols).is_err(), "{} should fail", source);
+        }
+    }
+
+    #[test]
+    fn lex_escaped_identifiers() {
+        let mut symbols = Symbols::new();
+        let tokens = lex(
+            "abc \\u0061bc a\\u{62}c \\u0069f ab\\u{1F600}",
+            &mut symbols,
+        );
+        assert!(tokens.is_err());
+
+        let tokens = lex("abc \\u0061bc a\\u{62}c \\u0069f", &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let words: Vec<_> = tokens
+            .iter()
+            .filter(|t| *t.kind == TokenKind::Word)
+            .collect();
+        assert_eq!(words.len(), 4);
+        assert_eq!(*words[0].extra, *words[1].extra);
+        assert_eq!(*words[0].extra, *words[2].extra);
+        assert_eq!(symbols.to_str(*words[3].extra), Some("if"));
+
+        for source in [
+            "\\u0030abc",
+            "a\\u002d",
+            "a\\u{110000}",
+            "a\\u{62 }",
+            "\\x61",
+            "a€",
+        ] {
+            assert!(lex(source, &mut symbols).is_err(), "{} should fail", source);
+        }
+    }
+
+    fn lex_kinds(source: &str) -> Vec<TokenKind> {
+        let mut symbols = Symbols::new();
+        let tokens = lex(source, &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        return tokens
+            .kind
+            .iter()
+            .copied()
+            .filter(|kind| !kind.is_trivia())
+            .collect();
+    }
+
+    #[test]
+    fn lex_escapes_at_chunk_boundaries() {
+        use TokenKind::*;
+
+        // Slide each case across the 32-byte boundaries of the first few
+        // chunks, so that the backslash runs get split at every offset.
+        let cases: &[(&str, &[TokenKind])] = &[
+            (r#""PAD\\";"#, &[String, Semicolon]),
+            (r#""PAD\\\\";"#, &[String, Semicolon]),
+            (r#""PAD\"";"#, &[String, Semicolon]),
+            (r#""PAD\\\"";"#, &[String, Semicolon]),
+            (r#""PAD\\" + "\\";"#, &[String, Add, String, Semicolon]),
+            ("\"PAD\\\nx\";", &[String, Semicolon]),
+            ("\"PAD\\\r\nx\";", &[String, Semicolon]),
+            (r#"`PAD\\`;"#, &[StrTemplate, Semicolon]),
+            (r#"`PAD\``;"#, &[StrTemplate, Semicolon]),
+            (r#"`PAD\\\``;"#, &[StrTemplate, Semicolon]),
+            (r#"`PAD\${a}`;"#, &[StrTemplate, Semicolon]),
+            (
+                r#"`PAD\\${a}`;"#,
+                &[StrTemplateBegin, Word, StrTemplateEnd, Semicolon],
+            ),
+            (r#"`PAD$\{a}`;"#, &[StrTemplate, Semicolon]),
+            (
+                r#"`PAD${a}PAD\\`;"#,
+                &[StrTemplateBegin, Word, StrTemplateEnd, Semicolon],
+            ),
+        ];
+
+        for pad in 0..100 {
+            let padding = "a".repeat(pad);
+            for (source, expected) in cases {
+                let source = source.replace("PAD", &padding);
+                assert_eq!(&lex_kinds(&source), expected, "{:?}", source);
+            }
+        }
+
+        for pad in 0..100 {
+            let padding = "a".repeat(pad);
+            for source in [r#""PAD\""#, r#""PAD\\\""#, "\"PAD\\\\\n\"", r#"`PAD\`"#] {
+                let source = source.replace("PAD", &padding);
+                let mut symbols = Symbols::new();
+                assert!(lex(&source, &mut symbols).is_err(), "{:?}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn lex_spans() {
+        let source = "let x = `a${y}b` // c\n\"str\";";
+        let mut symbols = Symbols::new();
+        let tokens = lex_with_options(
+            source,
+            &mut symbols,
+            LexOptions {
+                include_comments: true,
+                include_spacing: false,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.diagnostics)
+        .expect("doesn't error");
+
+        let texts: Vec<_> = tokens
+            .iter()
+            .filter(|t| *t.kind != TokenKind::Whitespace)
+            .map(|t| &source[*t.start as usize..*t.end as usize])
+            .collect();
+
+        assert_eq!(
+            texts,
+            ["let", "x", "=", "`a${", "y", "}b`", "// c", "\"str\"", ";"]
+        );
+    }
+
+    #[test]
+    fn lex_recovers_from_errors() {
+        use LexErrorCode::*;
+
+        let source = "a = \"abc\nb = 0x + 1__0;\n\u{1}c € \\u002d = `x${\"d\"}y";
+        let mut symbols = Symbols::new();
+        let opts = LexOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let result = lex_with_options(source, &mut symbols, opts)
+            .err()
+            .expect("should fail");
+
+        let codes: Vec<_> = result.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            [
+                NewlineInString,
+                InvalidNumber,
+                InvalidNumber,
+                UnrecognizedCharacter,
+                UnrecognizedCharacter,
+                InvalidIdentifier,
+                UnterminatedTemplate
+            ]
+        );
+
+        let texts: Vec<_> = result
+            .diagnostics
+            .iter()
+            .map(|d| &source[d.start as usize..d.end as usize])
+            .collect();
+        assert_eq!(
+            texts,
+            ["\"abc", "0x", "1__0", "\u{1}", "€", "\\u002d", "}y"]
+        );
+
+        let kinds: Vec<_> = result
+            .tokens
+            .kind
+            .iter()
+            .copied()
+            .filter(|kind| !kind.is_trivia())
+            .collect();
+
+        use TokenKind::*;
+        assert_eq!(
+            kinds,
+            [
+                Word,
+                Eq,
+                String,
+                Word,
+                Eq,
+                Number,
+                Add,
+                Number,
+                Semicolon,
+                Unknown,
+                Word,
+                Unknown,
+                Unknown,
+                Eq,
+                StrTemplateBegin,
+                String,
+                StrTemplateEnd
+            ]
+        );
+
+        // Without recovery, only the first problem gets reported.
+        let result = lex(source, &mut symbols).err().expect("should fail");
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].code, NewlineInString);
+    }
+
+    #[test]
+    fn lex_newline_before() {
+        let source = "a\nb /*\n*/ c /* */ d // x\n e\r\nf \"g\" h";
+        let mut symbols = Symbols::new();
+        let opts = LexOptions {
+            include_comments: true,
+            include_spacing: true,
+            ..Default::default()
+        };
+        let tokens = lex_with_options(source, &mut symbols, opts)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let flags: Vec<_> = tokens
+            .iter()
+            .filter(|t| !t.kind.is_trivia())
+            .map(|t| *t.newline_before)
+            .collect();
+
+        assert_eq!(flags, [false, true, true, false, true, true, false, false]);
+
+        // The parser's ASI decisions for `return` and `++` read these.
+        for (source, expected) in [("return\nx", [false, true]), ("a\n++b", [false, true])] {
+            let tokens = lex(source, &mut symbols)
+                .map_err(|e| e.diagnostics)
+                .expect("doesn't error");
+
+            let flags: Vec<_> = tokens.iter().map(|t| *t.newline_before).collect();
+            assert_eq!(flags[..2], expected, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn lex_contextual_keywords() {
+        let mut symbols = Symbols::new();
+        let tokens = lex(
+            "let type = 1; type A = B; declare \\u0074ype readonly of",
+            &mut symbols,
+        )
+        .map_err(|e| e.diagnostics)
+        .expect("doesn't error");
+
+        let keys: Vec<_> = tokens
+            .iter()
+            .filter(|t| *t.kind == TokenKind::Word)
+            .map(|t| CtxKey::from_symbol(*t.extra))
+            .collect();
+
+        use CtxKey::*;
+        assert_eq!(
+            keys,
+            [
+                Some(Type),
+                Some(Type),
+                None,
+                None,
+                Some(Declare),
+                Some(Type),
+                Some(Readonly),
+                Some(Of)
+            ]
+        );
+
+        assert_eq!(symbols.to_str(Accessor.symbol()), Some("accessor"));
+        assert_eq!(symbols.from_str("satisfies"), Some(Satisfies.symbol()));
+    }
+
+    #[test]
+    fn lex_unterminated_regex() {
+        for source in ["x = /abc", "x = /abc\n/", "x = /[/]", "x = /abc\\"] {
+            let mut symbols = Symbols::new();
+            let result = lex(source, &mut symbols).err().expect("should fail");
+            let error = &result.diagnostics[0];
+            assert_eq!(error.code, LexErrorCode::UnterminatedRegex);
+            assert_eq!(error.start, 4);
+            assert_eq!(
+                error.message,
+                "unterminated regular expression starting at byte 4"
+            );
+        }
+    }
+
+    #[test]
+    fn lex_string_values() {
+        let source = "'a\\n\\u{1F600}' \"x\\\ny\" `t\\x41${1}\\u{zz}${2}\r\n`";
+        let mut symbols = Symbols::new();
+        let tokens = lex(source, &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let strings = &symbols.strings;
+        // The strings, then the template pieces with a number after the first
+        // two of them.
+        let [a, b, begin, _, mid, _, end] = tokens.extra[..] else {
+            panic!("unexpected tokens: {:?}", tokens.kind);
+        };
+
+        assert_eq!(strings.to_str(a), Some("a\n\u{1F600}"));
+        assert_eq!(strings.to_str(b), Some("xy"));
+
+        let begin = strings.template(begin).unwrap();
+        assert_eq!(strings.to_str(begin.cooked.unwrap()), Some("tA"));
+        assert_eq!(strings.to_str(begin.raw), Some("t\\x41"));
+
+        let mid = strings.template(mid).unwrap();
+        assert_eq!(mid.cooked, None);
+        assert_eq!(strings.to_str(mid.raw), Some("\\u{zz}"));
+
+        let end = strings.template(end).unwrap();
+        assert_eq!(strings.to_str(end.cooked.unwrap()), Some("\n"));
+        assert_eq!(strings.to_str(end.raw), Some("\n"));
+
+        let source = "'ok' 'a\\x4g' \"\\u{110000}\"";
+        let opts = LexOptions {
+            recover: true,
+            ..Default::default()
+        };
+        let result = lex_with_options(source, &mut symbols, opts)
+            .err()
+            .expect("should fail");
+
+        let texts: Vec<_> = result
+            .diagnostics
+            .iter()
+            .map(|d| (d.code, &source[d.start as usize..d.end as usize]))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (LexErrorCode::InvalidEscape, "\\x4g"),
+                (LexErrorCode::InvalidEscape, "\\u")
+            ]
+        );
+        assert_eq!(result.tokens.extra[2], 0);
+    }
+
+    #[test]
+    fn lex_number_values() {
+        use crate::literals::NumberValue;
+
+        let mut symbols = Symbols::new();
+        let tokens = lex("1_0 .5 0x10 017 2n", &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let values: Vec<_> = tokens
+            .extra
+            .iter()
+            .map(|&id| symbols.numbers.get(id).cloned())
+            .collect();
+
+        assert_eq!(
+            values,
+            [
+                Some(NumberValue::Number(10.0)),
+                Some(NumberValue::Number(0.5)),
+                Some(NumberValue::Number(16.0)),
+                Some(NumberValue::Number(15.0)),
+                Some(NumberValue::BigInt(2u32.into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_file_start_and_html_comments() {
+        use TokenKind::*;
+
+        fn lex_with(
+            source: &str,
+            file: FileOptions,
+        ) -> Result<Vec<(TokenKind, &str)>, Vec<LexDiagnostic>> {
+            let mut symbols = Symbols::new();
+            let opts = LexOptions {
+                include_comments: true,
+                file,
+                ..Default::default()
+            };
+
+            let tokens = lex_with_options(source, &mut symbols, opts).map_err(|e| e.diagnostics)?;
+            let tokens = tokens
+                .iter()
+                .map(|token| {
+                    (
+                        *token.kind,
+                        &source[*token.start as usize..*token.end as usize],
+                    )
+                })
+                .collect();
+            return Ok(tokens);
+        }
+
+        let source = "\u{feff}#!/usr/bin/env node\nx";
+        assert_eq!(
+            lex_with(source, FileOptions::default()).expect("doesn't error"),
+            [(Hashbang, "#!/usr/bin/env node"), (Word, "x")]
+        );
+
+        // A hashbang anywhere else is just a `#`.
+        let kinds = lex_kinds("x\n#!y");
+        assert_eq!(kinds, [Word, Hash, Not, Word]);
+
+        let no_prelude = FileOptions {
+            allow_bom: false,
+            allow_hashbang: false,
+            ..Default::default()
+        };
+        assert!(lex_with("\u{feff}x", no_prelude).is_err());
+        let tokens = lex_with("#!x", no_prelude).expect("doesn't error");
+        assert_eq!(tokens, [(Hash, "#"), (Not, "!"), (Word, "x")]);
+
+        let source = "a <!-- b\nc\n  --> d\ne --> f";
+        let script = FileOptions {
+            allow_html_comments: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            lex_with(source, script).expect("doesn't error"),
+            [
+                (Word, "a"),
+                (LineComment, "<!-- b"),
+                (Word, "c"),
+                (LineComment, "--> d"),
+                (Word, "e"),
+                (MinusMinus, "--"),
+                (Gt, ">"),
+                (Word, "f")
+            ]
+        );
+
+        let kinds = lex_kinds("a <!-- b");
+        assert_eq!(kinds, [Word, Lt, Not, MinusMinus, Word]);
+    }
+
+    #[test]
+    fn lex_private_names() {
+        use TokenKind::*;
+
+        let source = "@dec #a; this.#a / 2; #\\u0061 a #if # 1";
+        let mut symbols = Symbols::new();
+        let tokens = lex(source, &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let tokens: Vec<_> = tokens
+            .iter()
+            .filter(|token| !token.kind.is_trivia())
+            .map(|token| (*token.kind, *token.extra))
+            .collect();
+
+        let private_a = symbols.from_str("#a").unwrap();
+        let a = symbols.from_str("a").unwrap();
+        let private_if = symbols.from_str("#if").unwrap();
+        assert_ne!(private_a, a);
+
+        assert_eq!(
+            tokens[..],
+            [
+                (At, 0),
+                (Word, symbols.from_str("dec").unwrap()),
+                (PrivateName, private_a),
+                (Semicolon, 0),
+                (Key(crate::tokens::Key::This), 0),
+                (Dot, 0),
+                (PrivateName, private_a),
+                (Div, 0),
+                (Number, tokens[8].1),
+                (Semicolon, 0),
+                (PrivateName, private_a),
+                (Word, a),
+                (PrivateName, private_if),
+                (Hash, 0),
+                (Number, tokens[14].1),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_jsx_values() {
+        let source = "<a aria-label='x\\n&lt;y' if>a &amp; b{/* c */}</a>";
+        let mut symbols = Symbols::new();
+        let opts = LexOptions {
+            jsx: true,
+            ..Default::default()
+        };
+
+        let tokens = lex_with_options(source, &mut symbols, opts)
+            .map_err(|e| e.diagnostics)
+            .expect("doesn't error");
+
+        let tokens: Vec<_> = tokens
+            .iter()
+            .filter(|token| !token.kind.is_trivia())
+            .map(|token| (*token.kind, *token.extra))
+            .collect();
+
+        let string = |id: u32| symbols.strings.to_str(id).unwrap();
+        let symbol = |id: u32| symbols.to_str(id).unwrap();
+
+        assert_eq!(tokens[2].0, TokenKind::JsxIdent);
+        assert_eq!(symbol(tokens[2].1), "aria-label");
+        assert_eq!(tokens[4].0, TokenKind::JsxString);
+        assert_eq!(string(tokens[4].1), "x\\n<y");
+        assert_eq!(tokens[5].0, TokenKind::JsxIdent);
+        assert_eq!(symbol(tokens[5].1), "if");
+        assert_eq!(tokens[7].0, TokenKind::JsxText);
+        assert_eq!(string(tokens[7].1), "a & b");
+        assert_eq!(tokens[9].0, TokenKind::RBrace);
+
+        let errors = |source: &str| {
+            let mut symbols = Symbols::new();
+            let result = lex_with_options(source, &mut symbols, opts);
+            let diagnostics = result.err().map(|e| e.diagnostics).unwrap_or_default();
+            return diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+        };
+
+        assert_eq!(errors("<a><b></b>"), [LexErrorCode::UnterminatedJsx]);
+        assert_eq!(errors("<a>{x"), [LexErrorCode::UnterminatedJsx]);
+        assert_eq!(errors("<a b='c>"), [LexErrorCode::UnterminatedString]);
+        assert_eq!(errors("<a b=1 />"), [LexErrorCode::UnrecognizedCharacter]);
+        assert_eq!(errors("a < b > c"), []);
+    }
+
+    #[test_resources("test/easy/*")]
+    fn relex_matches_lex(path: &str) {
+        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+        let opts = LexOptions {
+            include_comments: true,
+            recover: true,
+            jsx: path.ends_with(".tsx"),
+            ..Default::default()
+        };
+
+        let lex_all = |text: &str, symbols: &mut Symbols| {
+            return match lex_with_options(text, symbols, opts) {
+                Ok(tokens) => (tokens, Vec::new()),
+                Err(e) => (e.tokens, e.diagnostics),
+            };
+        };
+
+        // Numbers and template pieces aren't interned, so `extra` isn't
+        // compared.
+        let summary = |tokens: &TokenVec| {
+            let tokens = tokens.iter();
+            let tokens = tokens.map(|t| (*t.kind, *t.start, *t.end, *t.newline_before));
+            return tokens.collect::<Vec<_>>();
+        };
+
+        let mut symbols = Symbols::new();
+        let (old_tokens, old_diagnostics) = lex_all(&source, &mut symbols);
+
+        let insertions = [
+            "`", "${", "}", "{", "/", "*/", "'", "<", "</a>", "a", "1", "\n", "//",
+        ];
+        let starts = (0..=source.len()).step_by(5);
+        for start in starts.filter(|&i| source.is_char_boundary(i)) {
+            let deletions = (1..4).map(|len| (start + len, ""));
+            let insertions = insertions.iter().map(|&text| (start, text));
+
+            for (old_end, text) in deletions.chain(insertions) {
+                if old_end > source.len() || !source.is_char_boundary(old_end) {
+                    continue;
+                }
+
+                let new_text = format!("{}{}{}", &source[..start], text, &source[old_end..]);
+                let edit = TextEdit {
+                    start,
+                    old_end,
+                    new_end: start + text.len(),
+                };
+
+                let relexed = relex(
+                    &old_tokens,
+                    &old_diagnostics,
+                    &source,
+                    edit,
+                    &new_text,
+                    &mut symbols,
+                    opts,
+                );
+                let relexed = match relexed {
+                    Ok(tokens) => (tokens, Vec::new()),
+                    Err(e) => (e.tokens, e.diagnostics),
+                };
+                let expected = lex_all(&new_text, &mut symbols);
+
+                assert_eq!(
+                    (summary(&relexed.0), relexed.1),
+                    (summary(&expected.0), expected.1),
+                    "{:?} in {:?}",
+                    edit,
+                    new_text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn relex_lookahead_and_reuse() {
+        let opts = LexOptions {
+            jsx: true,
+            recover: true,
+            file: FileOptions {
+                allow_html_comments: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let relex_kinds = |old_text: &str, start: usize, old_end: usize, text: &str| {
+            let mut symbols = Symbols::new();
+            let old_tokens =
+                lex_with_options(old_text, &mut symbols, opts).unwrap_or_else(|e| e.tokens);
+
+            let new_text = format!("{}{}{}", &old_text[..start], text, &old_text[old_end..]);
+            let edit = TextEdit {
+                start,
+                old_end,
+                new_end: start + text.len(),
+            };
+
+            let tokens = relex(
+                &old_tokens,
+                &[],
+                old_text,
+                edit,
+                &new_text,
+                &mut symbols,
+                opts,
+            )
+            .unwrap_or_else(|e| e.tokens);
+            return tokens
+                .kind
+                .iter()
+                .copied()
+                .filter(|kind| !kind.is_trivia())
+                .collect::<Vec<_>>();
+        };
+
+        // The `<` before the edit changes meaning in both of these.
+        use TokenKind::*;
+        assert_eq!(relex_kinds("a<!-x", 4, 5, "-"), [Word]);
+        assert_eq!(
+            relex_kinds("f = <T a>(x) => x", 6, 8, ","),
+            [Word, Eq, Lt, Word, Comma, Gt, LParen, Word, RParen, Arrow, Word]
+        );
+
+        // Tokens after the edit are reused, so their numbers keep their ids.
+        let source = "1; 2; x; 3; 4; 5";
+        let mut symbols = Symbols::new();
+        let old_tokens = lex(source, &mut symbols)
+            .map_err(|e| e.diagnostics)
+            .unwrap();
+        let edit = TextEdit {
+            start: 6,
+            old_end: 7,
+            new_end: 9,
+        };
+
+        let new_tokens = relex(
+            &old_tokens,
+            &[],
+            source,
+            edit,
+            "1; 2; yyy; 3; 4; 5",
+            &mut symbols,
+            opts,
+        )
+        .map_err(|e| e.diagnostics)
+        .unwrap();
+        assert_eq!(new_tokens.extra.last(), old_tokens.extra.last());
+        assert_eq!(*new_tokens.start.last().unwrap(), 17);
+
+        // Diagnostics on either side of the edit are kept, shifted to match.
+        let source = "'a\n1; 2; x; 3; 4; 5; 'b\n";
+        let new_text = "'a\n1; 2; yyy; 3; 4; 5; 'b\n";
+        let result = lex_with_options(source, &mut symbols, opts).err().unwrap();
+        let edit = TextEdit {
+            start: 9,
+            old_end: 10,
+            new_end: 12,
+        };
+
+        let relexed = relex(
+            &result.tokens,
+            &result.diagnostics,
+            source,
+            edit,
+            new_text,
+            &mut symbols,
+            opts,
+        )
+        .err()
+        .unwrap();
+        let expected = lex_with_options(new_text, &mut symbols, opts)
+            .err()
+            .unwrap();
+        assert_eq!(relexed.diagnostics.len(), 2);
+        assert_eq!(relexed.diagnostics, expected.diagnostics);
+    }
 }
@@ -1,16 +1,25 @@
 #![allow(dead_code)]
-
-#![feature(test)]
-#![feature(portable_simd)]
+// Benchmarks and `portable_simd` both need nightly, so they're only on with
+// the `simd` feature.
+#![cfg_attr(all(test, feature = "simd"), feature(test))]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
 
 pub mod ast;
+pub mod entities;
 pub mod lexer;
+pub mod line_index;
+pub mod literals;
+pub mod parallel;
+pub mod scan;
+#[cfg(feature = "simd")]
 pub mod simd;
 pub mod tokens;
 pub mod util;
 
 pub use ast::*;
-pub use lexer::{lex, lex_with_options, LexOptions};
+pub use lexer::{lex, lex_with_options, relex, LexOptions, TextEdit};
+pub use line_index::{LineCol, LineIndex};
+pub use parallel::lex_parallel;
 pub use util::Symbols;
 
 #[cfg(test)]
@@ -0,0 +1,123 @@
+use crate::scan::{DefaultScanner, Scanner};
+
+/// Line and column of a position in the source, both 0-indexed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Maps byte offsets to line/column positions and back. `\n`, `\r\n` and a
+/// lone `\r` each count as one line break.
+pub struct LineIndex {
+    /// Byte offset of the start of each line; the first entry is always 0.
+    line_starts: Vec<u32>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+
+        let mut line_starts = Vec::with_capacity(count_newlines(bytes) + 1);
+        line_starts.push(0);
+
+        let mut index = DefaultScanner::find_line_break(bytes, 0);
+        while index < bytes.len() {
+            if bytes[index] == b'\r' && bytes.get(index + 1) == Some(&b'\n') {
+                index += 1;
+            }
+
+            index += 1;
+            line_starts.push(index as u32);
+            index = DefaultScanner::find_line_break(bytes, index);
+        }
+
+        return Self { line_starts };
+    }
+
+    pub fn line_count(&self) -> usize {
+        return self.line_starts.len();
+    }
+
+    pub fn line_start(&self, line: u32) -> Option<u32> {
+        return self.line_starts.get(line as usize).copied();
+    }
+
+    /// Column is in bytes from the start of the line.
+    pub fn line_col(&self, offset: u32) -> LineCol {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+
+        return LineCol {
+            line: line as u32,
+            col: offset - self.line_starts[line],
+        };
+    }
+
+    /// Like `line_col`, but the column is in UTF-16 code units, which is what
+    /// source maps and LSP clients expect. `offset` should be a token
+    /// boundary; an offset inside a multi-byte character is rounded down to
+    /// the start of that character.
+    pub fn line_col_utf16(&self, text: &str, offset: u32) -> LineCol {
+        let LineCol { line, col } = self.line_col(offset);
+        let start = self.line_starts[line as usize] as usize;
+
+        let mut end = start + col as usize;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let prefix = &text[start..end];
+
+        return LineCol {
+            line,
+            col: prefix.encode_utf16().count() as u32,
+        };
+    }
+
+    pub fn offset(&self, pos: LineCol) -> Option<u32> {
+        return Some(self.line_start(pos.line)? + pos.col);
+    }
+}
+
+/// Counts the `\n` bytes in `bytes`.
+pub fn count_newlines(bytes: &[u8]) -> usize {
+    return DefaultScanner::count_newlines(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_index_positions() {
+        let text = "ab\ncd\r\nef\rg\n\nh€i\n".repeat(5);
+        let index = LineIndex::new(&text);
+
+        assert_eq!(count_newlines(text.as_bytes()), 25);
+        assert_eq!(index.line_count(), 31);
+
+        // Check against a dumb scalar version for every offset.
+        let (mut line, mut col) = (0, 0);
+        let bytes = text.as_bytes();
+        for offset in 0..bytes.len() {
+            let pos = LineCol { line, col };
+            assert_eq!(index.line_col(offset as u32), pos, "offset {}", offset);
+            assert_eq!(index.offset(pos), Some(offset as u32));
+
+            col += 1;
+            let crlf = bytes[offset] == b'\r' && bytes.get(offset + 1) == Some(&b'\n');
+            if (bytes[offset] == b'\n' || bytes[offset] == b'\r') && !crlf {
+                line += 1;
+                col = 0;
+            }
+        }
+
+        let i = text.find('i').unwrap() as u32;
+        assert_eq!(index.line_col(i), LineCol { line: 5, col: 4 });
+        assert_eq!(index.line_col_utf16(&text, i), LineCol { line: 5, col: 2 });
+        assert_eq!(
+            index.line_col_utf16(&text, i - 1),
+            LineCol { line: 5, col: 1 }
+        );
+    }
+}
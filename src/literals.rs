@@ -0,0 +1,533 @@
+use crate::entities::lookup_entity;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use std::collections::HashMap;
+
+/// Interned values of string literals and template pieces. Values are stored
+/// as WTF-8, i.e. UTF-8 that also allows lone surrogates, since `"\uD800"` is
+/// a perfectly valid JS string. Ids start at 1, like symbol ids.
+#[derive(Default)]
+pub struct StringTable {
+    to_id: HashMap<Box<[u8]>, u32>,
+    values: Vec<Box<[u8]>>,
+    templates: Vec<TemplatePiece>,
+}
+
+/// The strings for one `StrTemplate*` token, which is what its `extra` points
+/// to. `cooked` is `None` when the piece has an invalid escape, which is only
+/// allowed in tagged templates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TemplatePiece {
+    pub cooked: Option<u32>,
+    pub raw: u32,
+}
+
+impl StringTable {
+    pub fn add(&mut self, value: &[u8]) -> u32 {
+        if let Some(id) = self.to_id.get(value) {
+            return *id;
+        }
+
+        let id = self.values.len() as u32 + 1;
+        let value: Box<[u8]> = value.into();
+        self.to_id.insert(value.clone(), id);
+        self.values.push(value);
+        return id;
+    }
+
+    pub fn add_template(&mut self, cooked: Option<&[u8]>, raw: &[u8]) -> u32 {
+        let piece = TemplatePiece {
+            cooked: cooked.map(|cooked| self.add(cooked)),
+            raw: self.add(raw),
+        };
+
+        self.templates.push(piece);
+        return self.templates.len() as u32;
+    }
+
+    pub fn get(&self, id: u32) -> Option<&[u8]> {
+        let index = (id as usize).checked_sub(1)?;
+        return self.values.get(index).map(|a| &**a);
+    }
+
+    /// Returns `None` for unknown ids and for values with lone surrogates.
+    pub fn to_str(&self, id: u32) -> Option<&str> {
+        return core::str::from_utf8(self.get(id)?).ok();
+    }
+
+    pub fn template(&self, id: u32) -> Option<TemplatePiece> {
+        let index = (id as usize).checked_sub(1)?;
+        return self.templates.get(index).copied();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NumberValue {
+    Number(f64),
+    BigInt(BigUint),
+}
+
+/// Values of numeric literals, in the order they were lexed. Unlike strings
+/// these aren't interned. Ids start at 1.
+#[derive(Default)]
+pub struct NumberTable {
+    values: Vec<NumberValue>,
+}
+
+impl NumberTable {
+    pub fn add(&mut self, value: NumberValue) -> u32 {
+        self.values.push(value);
+        return self.values.len() as u32;
+    }
+
+    pub fn get(&self, id: u32) -> Option<&NumberValue> {
+        let index = (id as usize).checked_sub(1)?;
+        return self.values.get(index);
+    }
+
+    /// The value as a JS number; BigInts get converted like `Number(1n)`.
+    pub fn to_f64(&self, id: u32) -> Option<f64> {
+        match self.get(id)? {
+            NumberValue::Number(value) => return Some(*value),
+            NumberValue::BigInt(value) => return value.to_f64(),
+        }
+    }
+}
+
+/// Computes the value of a numeric literal that the lexer already validated.
+/// Decimals go through the standard library's correctly rounded parser; other
+/// radixes are exact integers, rounded half-to-even if they don't fit.
+pub fn parse_number(text: &[u8]) -> NumberValue {
+    let digits: Vec<u8> = text.iter().copied().filter(|&c| c != b'_').collect();
+
+    let (radix, digits) = match digits.get(..2) {
+        Some(b"0x" | b"0X") => (16, &digits[2..]),
+        Some(b"0o" | b"0O") => (8, &digits[2..]),
+        Some(b"0b" | b"0B") => (2, &digits[2..]),
+
+        // Annex B legacy octal, like `017`. `019` is a decimal.
+        Some([b'0', b'0'..=b'7']) if digits.iter().all(|c| matches!(c, b'0'..=b'7')) => {
+            (8, &digits[1..])
+        }
+
+        _ => (10, &digits[..]),
+    };
+
+    if let Some((b'n', digits)) = digits.split_last() {
+        let value = BigUint::parse_bytes(digits, radix).unwrap_or_default();
+        return NumberValue::BigInt(value);
+    }
+
+    if radix != 10 {
+        let value = BigUint::parse_bytes(digits, radix).unwrap_or_default();
+        return NumberValue::Number(value.to_f64().unwrap_or(f64::INFINITY));
+    }
+
+    let text = core::str::from_utf8(digits).unwrap_or("");
+    return NumberValue::Number(text.parse().unwrap_or(f64::NAN));
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EscapeError {
+    /// Byte offsets of the bad escape within the text that was cooked.
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+/// Decodes the escapes in the text between a literal's delimiters, writing
+/// WTF-8 to `out`. Strings allow Annex B's legacy octal escapes; templates
+/// don't, and normalize `\r\n` and `\r` to `\n`.
+pub fn cook_string(raw: &str, template: bool, out: &mut Vec<u8>) -> Result<(), EscapeError> {
+    let bytes = raw.as_bytes();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let start = index;
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'\r' if template => {
+                index += 1;
+                if bytes.get(index) == Some(&b'\n') {
+                    index += 1;
+                }
+
+                out.push(b'\n');
+                continue;
+            }
+            _ => {
+                // Copy everything up to the next interesting byte at once.
+                let len = bytes[index..]
+                    .iter()
+                    .position(|&c| c == b'\\' || c == b'\r')
+                    .unwrap_or(bytes.len() - index)
+                    .max(1);
+
+                out.extend_from_slice(&bytes[index..index + len]);
+                index += len;
+                continue;
+            }
+        }
+
+        let error = |end: usize, message: String| EscapeError {
+            start,
+            end,
+            message,
+        };
+
+        let Some(&next) = bytes.get(index) else {
+            return Err(error(index, format!("escape sequence at end of literal")));
+        };
+
+        index += 1;
+        match next {
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'v' => out.push(0x0b),
+
+            // Line continuations
+            b'\n' => {}
+            b'\r' => {
+                if bytes.get(index) == Some(&b'\n') {
+                    index += 1;
+                }
+            }
+
+            b'x' => {
+                let digits = bytes.get(index..index + 2).unwrap_or(&[]);
+                let Some(value) = parse_hex(digits).filter(|_| digits.len() == 2) else {
+                    let end = (index + 2).min(bytes.len());
+                    return Err(error(
+                        end,
+                        format!("`\\x` must be followed by 2 hex digits"),
+                    ));
+                };
+
+                index += 2;
+                push_code_unit(out, value);
+            }
+
+            b'u' => {
+                let (value, len) = parse_code_unit_escape(&bytes[index..])
+                    .ok_or_else(|| error(index, format!("invalid unicode escape sequence")))?;
+
+                index += len;
+                push_code_unit(out, value);
+            }
+
+            b'0'..=b'7' => {
+                let next_is_digit = bytes.get(index).is_some_and(|c| c.is_ascii_digit());
+                if next == b'0' && !next_is_digit {
+                    out.push(0);
+                    continue;
+                }
+
+                if template {
+                    return Err(error(
+                        index,
+                        format!("octal escape sequences aren't allowed in templates"),
+                    ));
+                }
+
+                // `\377` is the largest three-digit escape; `\400` is `\40`
+                // followed by a `0`.
+                let max_len = if next <= b'3' { 3 } else { 2 };
+                let mut value = (next - b'0') as u32;
+                let mut len = 1;
+                while let Some(c @ b'0'..=b'7') = bytes.get(index) {
+                    if len == max_len {
+                        break;
+                    }
+
+                    value = value * 8 + (c - b'0') as u32;
+                    len += 1;
+                    index += 1;
+                }
+
+                push_code_unit(out, value);
+            }
+
+            b'8' | b'9' => {
+                if template {
+                    return Err(error(
+                        index,
+                        format!("`\\{}` isn't allowed in templates", char::from(next)),
+                    ));
+                }
+
+                out.push(next);
+            }
+
+            // Any other character, including U+2028 and U+2029 which are
+            // continuations, escapes to itself.
+            _ => {
+                let c = raw[index - 1..].chars().next().unwrap_or('\0');
+                index += c.len_utf8() - 1;
+
+                if c != '\u{2028}' && c != '\u{2029}' {
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// The raw value of a template piece is its source text, with line
+/// terminators normalized to `\n`.
+pub fn template_raw(raw: &str, out: &mut Vec<u8>) {
+    let bytes = raw.as_bytes();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\r' => {
+                index += 1;
+                if bytes.get(index) == Some(&b'\n') {
+                    index += 1;
+                }
+
+                out.push(b'\n');
+            }
+            c => {
+                out.push(c);
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Decodes the character references in JSX text and attribute strings, e.g.
+/// `&amp;`, `&#123;` and `&#x7B;`. JSX has no backslash escapes, and anything
+/// that isn't a valid reference is left as it is.
+pub fn decode_jsx_entities(raw: &[u8], out: &mut Vec<u8>) {
+    let mut index = 0;
+    while let Some(&c) = raw.get(index) {
+        if c == b'&' {
+            if let Some((c, len)) = parse_jsx_entity(&raw[index..]) {
+                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                index += len;
+                continue;
+            }
+        }
+
+        out.push(c);
+        index += 1;
+    }
+}
+
+// Parses a reference at the start of `bytes`, returning the character and the
+// length of the reference, `&` and `;` included.
+fn parse_jsx_entity(bytes: &[u8]) -> Option<(char, usize)> {
+    // The longest names are 8 bytes, e.g. `thetasym`, and the longest numbers
+    // are `#x10FFFF` and `#1114111`.
+    let end = bytes.iter().take(10).position(|&c| c == b';')?;
+    let value = match &bytes[1..end] {
+        [b'#', b'x' | b'X', digits @ ..] => parse_hex(digits)?,
+        [b'#', digits @ ..] => {
+            if digits.is_empty() || !digits.iter().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+
+            core::str::from_utf8(digits).ok()?.parse().ok()?
+        }
+        name => return Some((lookup_entity(name)?, end + 1)),
+    };
+
+    return Some((char::from_u32(value)?, end + 1));
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || !digits.iter().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let digits = core::str::from_utf8(digits).ok()?;
+    return u32::from_str_radix(digits, 16).ok();
+}
+
+// Parses what follows `\u`, which can be a surrogate, unlike in identifiers.
+fn parse_code_unit_escape(bytes: &[u8]) -> Option<(u32, usize)> {
+    if bytes.first() == Some(&b'{') {
+        // Only the hex digits are searched for the `}`; see
+        // `parse_unicode_escape`.
+        let close = 1 + bytes[1..]
+            .iter()
+            .take_while(|c| c.is_ascii_hexdigit())
+            .count();
+        if bytes.get(close) != Some(&b'}') {
+            return None;
+        }
+
+        let value = parse_hex(&bytes[1..close])?;
+        if value > 0x10ffff {
+            return None;
+        }
+
+        return Some((value, close + 1));
+    }
+
+    let digits = bytes.get(..4)?;
+    return Some((parse_hex(digits)?, 4));
+}
+
+/// Appends a code point, or a surrogate, as WTF-8. A trail surrogate right
+/// after a lead surrogate gets combined with it, so `"\uD83D\uDE00"` is
+/// the same string as `"\u{1F600}"`.
+fn push_code_unit(out: &mut Vec<u8>, value: u32) {
+    if let (0xdc00..=0xdfff, [.., 0xed, lead @ 0xa0..=0xaf, trail]) = (value, &out[..]) {
+        let high = 0xd000 | ((*lead as u32 & 0x3f) << 6) | (*trail as u32 & 0x3f);
+        let combined = 0x10000 + ((high - 0xd800) << 10) + (value - 0xdc00);
+        out.truncate(out.len() - 3);
+        push_code_unit(out, combined);
+        return;
+    }
+
+    match value {
+        0..=0x7f => out.push(value as u8),
+        0x80..=0x7ff => {
+            out.extend_from_slice(&[0xc0 | (value >> 6) as u8, 0x80 | (value & 0x3f) as u8])
+        }
+        0x800..=0xffff => out.extend_from_slice(&[
+            0xe0 | (value >> 12) as u8,
+            0x80 | ((value >> 6) & 0x3f) as u8,
+            0x80 | (value & 0x3f) as u8,
+        ]),
+        _ => out.extend_from_slice(&[
+            0xf0 | (value >> 18) as u8,
+            0x80 | ((value >> 12) & 0x3f) as u8,
+            0x80 | ((value >> 6) & 0x3f) as u8,
+            0x80 | (value & 0x3f) as u8,
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cook(raw: &str, template: bool) -> Result<Vec<u8>, EscapeError> {
+        let mut out = Vec::new();
+        cook_string(raw, template, &mut out)?;
+        return Ok(out);
+    }
+
+    #[test]
+    fn cook_escapes() {
+        let cases: &[(&str, &str)] = &[
+            (r"plain", "plain"),
+            (r"a\nb\tc", "a\nb\tc"),
+            (r"\b\f\v\0", "\u{8}\u{c}\u{b}\0"),
+            (r#"\'\"\\\q"#, "'\"\\q"),
+            (r"\x41B\u{43}\u{1F600}", "ABC😀"),
+            (r"\u{0000041}", "A"),
+            (r"😀", "😀"),
+            ("a\\\nb\\\r\nc\\\u{2028}d", "abcd"),
+            (r"\101\7\08\400\8", "A\u{7}\u{0}8\u{20}08"),
+            ("é\\é", "éé"),
+        ];
+
+        for &(raw, cooked) in cases {
+            assert_eq!(cook(raw, false), Ok(cooked.as_bytes().to_vec()), "{}", raw);
+        }
+
+        assert_eq!(cook("a\r\nb\rc", true), Ok(b"a\nb\nc".to_vec()));
+
+        // Lone surrogates are kept as WTF-8.
+        assert_eq!(cook(r"\uD800", false), Ok(vec![0xed, 0xa0, 0x80]));
+
+        let invalid_strings = [
+            r"\x4",
+            r"\xZZ",
+            r"\u12",
+            r"\u{}",
+            r"\u{110000}",
+            r"\u{41",
+            r"\u{41 }",
+            "\\",
+        ];
+        for raw in invalid_strings {
+            assert!(cook(raw, false).is_err(), "{} should fail", raw);
+        }
+
+        for raw in [r"\01", r"\1", r"\8"] {
+            assert!(
+                cook(raw, true).is_err(),
+                "{} should fail in a template",
+                raw
+            );
+        }
+
+        let error = cook(r"ab\x4g", false).unwrap_err();
+        assert_eq!((error.start, error.end), (2, 6));
+    }
+
+    #[test]
+    fn number_values() {
+        let number = |text: &str| match parse_number(text.as_bytes()) {
+            NumberValue::Number(value) => value,
+            NumberValue::BigInt(value) => panic!("{} isn't a number: {}", text, value),
+        };
+
+        assert_eq!(number("0"), 0.0);
+        assert_eq!(number("1_000.5e-3"), 1.0005);
+        assert_eq!(number(".5"), 0.5);
+        assert_eq!(number("5."), 5.0);
+        assert_eq!(number("0xFF"), 255.0);
+        assert_eq!(number("0o17"), 15.0);
+        assert_eq!(number("0b1010"), 10.0);
+        assert_eq!(number("017"), 15.0);
+        assert_eq!(number("019"), 19.0);
+        assert_eq!(number("1e400"), f64::INFINITY);
+
+        // Ties round to even, and anything past the tie rounds up.
+        assert_eq!(number("9007199254740993"), 9007199254740992.0);
+        assert_eq!(number("9007199254740995"), 9007199254740996.0);
+        assert_eq!(number("9007199254740993.0000000001"), 9007199254740994.0);
+        assert_eq!(number("0x20000000000001"), 9007199254740992.0);
+        assert_eq!(number("0x20000000000003"), 9007199254740996.0);
+        assert_eq!(number("0x200000000000010000"), 5.902958103587057e20);
+        assert_eq!(number("0x200000000000010001"), 5.902958103587058e20);
+        assert_eq!(number("0.1"), 0.1);
+        assert_eq!(number("2.2250738585072011e-308"), 2.225073858507201e-308);
+
+        let bigint = |text: &str| match parse_number(text.as_bytes()) {
+            NumberValue::BigInt(value) => value.to_string(),
+            NumberValue::Number(value) => panic!("{} isn't a BigInt: {}", text, value),
+        };
+
+        assert_eq!(bigint("0n"), "0");
+        assert_eq!(bigint("123_456n"), "123456");
+        assert_eq!(
+            bigint("0xffff_ffff_ffff_ffff_ffffn"),
+            "1208925819614629174706175"
+        );
+        assert_eq!(bigint("0b11n"), "3");
+    }
+
+    #[test]
+    fn jsx_entities() {
+        let decode = |raw: &str| {
+            let mut out = Vec::new();
+            decode_jsx_entities(raw.as_bytes(), &mut out);
+            return String::from_utf8(out).unwrap();
+        };
+
+        assert_eq!(decode("a &amp; b"), "a & b");
+        assert_eq!(decode("&lt;&gt;&quot;&apos;&nbsp;"), "<>\"'\u{a0}");
+        assert_eq!(decode("&thetasym;&AElig;&aelig;"), "\u{3d1}\u{c6}\u{e6}");
+        assert_eq!(decode("&#123;&#x7B;&#X1F600;"), "{{\u{1F600}");
+        assert_eq!(decode(r"\n"), r"\n");
+
+        // Anything that isn't a reference stays as it is.
+        assert_eq!(
+            decode("& &amp &bogus; &#; &#x; &#xD800; &#x110000;"),
+            "& &amp &bogus; &#; &#x; &#xD800; &#x110000;"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use crate::lexer::*;
+use crate::scan::{DefaultScanner, Scanner};
+use crate::tokens::*;
+use crate::util::Symbols;
+use std::ops::Range;
+
+/// Files smaller than this aren't worth splitting up.
+const MIN_CHUNK_SIZE: usize = 1 << 20;
+
+/// Lexes big files in chunks on several threads, giving exactly what `lex`
+/// would, symbol ids included. Smaller files just get lexed on this thread.
+#[allow(clippy::result_large_err)]
+pub fn lex_parallel(
+    text: &str,
+    symbols: &mut Symbols,
+    opts: LexOptions,
+) -> Result<TokenVec, LexResult> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = (text.len() / threads).max(MIN_CHUNK_SIZE);
+    return lex_in_chunks::<DefaultScanner>(text, symbols, opts, chunk_size);
+}
+
+/// What a chunk's lexer is told about everything before it.
+struct ChunkStart {
+    index: usize,
+    context_stack: Vec<LexContext>,
+    newline_before: bool,
+    /// The last token before the chunk that isn't trivia, for
+    /// `regex_allowed`.
+    prev: Option<Token>,
+}
+
+struct Chunk {
+    tokens: TokenVec,
+    diagnostics: Vec<LexDiagnostic>,
+    /// Ids in `tokens` point in here until they get merged.
+    symbols: Symbols,
+    /// Where lexing stopped, which is the first token boundary at or after
+    /// the end of the chunk.
+    end: usize,
+    context_stack: Vec<LexContext>,
+    newline_before: bool,
+}
+
+#[allow(clippy::result_large_err)]
+fn lex_in_chunks<S: Scanner>(
+    text: &str,
+    symbols: &mut Symbols,
+    opts: LexOptions,
+    chunk_size: usize,
+) -> Result<TokenVec, LexResult> {
+    check_file_size(text)?;
+
+    let bytes = text.as_bytes();
+    let starts = chunk_starts::<S>(bytes, chunk_size);
+    if starts.len() == 1 {
+        return lex_with_scanner::<S>(text, symbols, opts);
+    }
+
+    let ends = starts[1..].iter().copied().chain([bytes.len()]);
+    let ranges: Vec<Range<usize>> = starts
+        .iter()
+        .copied()
+        .zip(ends)
+        .map(|(s, e)| s..e)
+        .collect();
+
+    // Every chunk guesses that it starts at a token boundary, with nothing
+    // open and a line break right before it.
+    let mut chunks: Vec<Chunk> = std::thread::scope(|scope| {
+        let handles: Vec<_> = ranges
+            .iter()
+            .map(|range| {
+                let start = ChunkStart {
+                    index: range.start,
+                    context_stack: Vec::new(),
+                    newline_before: range.start != 0,
+                    prev: None,
+                };
+
+                let end = range.end;
+                return scope.spawn(move || lex_chunk::<S>(bytes, start, end, opts));
+            })
+            .collect();
+
+        return handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+    });
+
+    // Then each guess gets checked against where the chunk before it actually
+    // ended, and the chunk gets lexed again if it was wrong.
+    let mut prev: Option<Token> = None;
+    for index in 1..chunks.len() {
+        let before = &chunks[index - 1];
+        if let Some(last) = last_significant(&before.tokens) {
+            prev = Some(last);
+        }
+
+        if !guess_was_right(before, &chunks[index], ranges[index].start, prev.as_ref()) {
+            let start = ChunkStart {
+                index: before.end,
+                context_stack: before.context_stack.clone(),
+                newline_before: before.newline_before,
+                prev,
+            };
+
+            chunks[index] = lex_chunk::<S>(bytes, start, ranges[index].end, opts);
+        }
+    }
+
+    // Chunks always recover, so without `recover` there's a partial token list
+    // to get right, which is easiest to do from scratch.
+    let has_errors = chunks.iter().any(|chunk| !chunk.diagnostics.is_empty());
+    if has_errors && !opts.recover {
+        return lex_with_scanner::<S>(text, symbols, opts);
+    }
+
+    // Ids get assigned in the same order that lexing the whole file would.
+    let mut tokens = TokenVec::with_capacity(chunks.iter().map(|c| c.tokens.len()).sum());
+    let mut diagnostics = Vec::new();
+    for chunk in chunks {
+        for token in &chunk.tokens {
+            let mut token = token.to_owned();
+            token.extra = reintern(token.kind, token.extra, &chunk.symbols, symbols);
+            tokens.push(token);
+        }
+
+        diagnostics.extend(chunk.diagnostics);
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(LexResult {
+            tokens,
+            diagnostics,
+        });
+    }
+
+    return Ok(tokens);
+}
+
+// Chunks start after a `\n`, which is usually between tokens and outside of
+// any template or JSX. The whitespace after it gets skipped too, since the
+// chunk before lexes all of it as one token.
+fn chunk_starts<S: Scanner>(bytes: &[u8], chunk_size: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    while let Some(&start) = starts.last() {
+        let Some(rest) = bytes.get(start + chunk_size..) else {
+            break;
+        };
+
+        let Some(len) = rest.iter().position(|&c| c == b'\n') else {
+            break;
+        };
+
+        let (next, _) = S::skip_whitespace(bytes, start + chunk_size + len + 1);
+        if next >= bytes.len() {
+            break;
+        }
+
+        starts.push(next);
+    }
+
+    return starts;
+}
+
+fn lex_chunk<S: Scanner>(bytes: &[u8], start: ChunkStart, end: usize, opts: LexOptions) -> Chunk {
+    let mut symbols = Symbols::new();
+    let mut state = LexState {
+        index: start.index,
+        begin_index: start.index,
+        context_stack: start.context_stack,
+        newline_before: start.newline_before,
+        opts: LexOptions {
+            recover: true,
+            ..opts
+        },
+        ..Default::default()
+    };
+
+    if start.index == 0 {
+        lex_file_start::<S>(&mut state, bytes);
+    }
+
+    if let Some(prev) = start.prev {
+        state.tokens.push(prev);
+    }
+
+    while state.index < end {
+        if let Ok(false) | Err(LexAbort) = lex_next::<S>(&mut state, bytes, &mut symbols) {
+            break;
+        }
+    }
+
+    if start.prev.is_some() {
+        state.tokens.remove(0);
+    }
+
+    // Only the last chunk sees the real end of the file.
+    if end == bytes.len() {
+        let _ = lex_file_end(&mut state);
+    }
+
+    return Chunk {
+        tokens: state.tokens,
+        diagnostics: state.diagnostics,
+        symbols,
+        end: state.index,
+        context_stack: state.context_stack,
+        newline_before: state.newline_before,
+    };
+}
+
+fn guess_was_right(before: &Chunk, chunk: &Chunk, start: usize, prev: Option<&Token>) -> bool {
+    if before.end != start || !before.context_stack.is_empty() || !before.newline_before {
+        return false;
+    }
+
+    // The guess had nothing before the chunk, which lexes like anything that
+    // allows a regex. Otherwise only the first token could have been wrong.
+    if regex_allowed(prev.map(|token| token.kind).as_slice()) {
+        return true;
+    }
+
+    let first = chunk.tokens.kind.iter().find(|kind| !kind.is_trivia());
+    return !matches!(
+        first,
+        Some(TokenKind::Regex | TokenKind::Div | TokenKind::DivEq | TokenKind::Lt)
+    );
+}
+
+fn last_significant(tokens: &TokenVec) -> Option<Token> {
+    let index = tokens.kind.iter().rposition(|kind| !kind.is_trivia())?;
+    return Some(tokens.index(index).to_owned());
+}
+
+// Interns whatever `extra` points to in `from` into `to`, which is exactly
+// what lexing the token against `to` would have done.
+fn reintern(kind: TokenKind, extra: u32, from: &Symbols, to: &mut Symbols) -> u32 {
+    match kind {
+        TokenKind::Word | TokenKind::PrivateName | TokenKind::JsxIdent => {
+            return to.add_str(from.to_str(extra).unwrap());
+        }
+
+        // Strings with bad escapes don't get a value.
+        TokenKind::String | TokenKind::JsxText | TokenKind::JsxString if extra != 0 => {
+            return to.strings.add(from.strings.get(extra).unwrap());
+        }
+
+        TokenKind::StrTemplate
+        | TokenKind::StrTemplateBegin
+        | TokenKind::StrTemplateMid
+        | TokenKind::StrTemplateEnd => {
+            let piece = from.strings.template(extra).unwrap();
+            let cooked = piece.cooked.map(|id| from.strings.get(id).unwrap());
+            return to
+                .strings
+                .add_template(cooked, from.strings.get(piece.raw).unwrap());
+        }
+
+        // So do invalid numbers.
+        TokenKind::Number
+        | TokenKind::OctNumber
+        | TokenKind::HexNumber
+        | TokenKind::BinNumber
+        | TokenKind::BigInt
+            if extra != 0 =>
+        {
+            return to.numbers.add(from.numbers.get(extra).unwrap().clone());
+        }
+
+        _ => return extra,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tests::*;
+
+    fn check_chunks_agree(source: &str, opts: LexOptions) {
+        fn flatten(result: Result<TokenVec, LexResult>) -> (Vec<Token>, Vec<LexDiagnostic>) {
+            let (tokens, diagnostics) = match result {
+                Ok(tokens) => (tokens, Vec::new()),
+                Err(e) => (e.tokens, e.diagnostics),
+            };
+
+            return (tokens.iter().map(|t| t.to_owned()).collect(), diagnostics);
+        }
+
+        fn values(symbols: &Symbols) -> (Vec<String>, Vec<Vec<u8>>, Vec<String>) {
+            let strings = (1..).map_while(|id| symbols.strings.get(id).map(|s| s.to_vec()));
+            let numbers = (1..).map_while(|id| symbols.numbers.get(id).map(|n| format!("{:?}", n)));
+            return (
+                symbols.to_name.clone(),
+                strings.collect(),
+                numbers.collect(),
+            );
+        }
+
+        let mut expected_symbols = Symbols::new();
+        let expected = flatten(lex_with_options(source, &mut expected_symbols, opts));
+
+        for chunk_size in [1, 2, 3, 5, 8, 13, 40] {
+            let mut symbols = Symbols::new();
+            let result = lex_in_chunks::<DefaultScanner>(source, &mut symbols, opts, chunk_size);
+
+            assert_eq!(flatten(result), expected, "chunk size {}", chunk_size);
+            assert_eq!(values(&symbols), values(&expected_symbols));
+        }
+    }
+
+    #[test_resources("test/**/*.ts*")]
+    fn lex_chunks_agree(path: &str) {
+        let source = std::fs::read_to_string(path).expect("Should have been able to read the file");
+        let opts = LexOptions {
+            jsx: path.ends_with(".tsx"),
+            ..Default::default()
+        };
+
+        check_chunks_agree(&source, opts);
+    }
+
+    #[test]
+    fn lex_chunks_guess_right_on_indented_code() {
+        let source = "if (a) {\n    b();\n\n\t\tc();\n}\n";
+        let bytes = source.as_bytes();
+
+        for include_spacing in [true, false] {
+            let opts = LexOptions {
+                include_spacing,
+                ..Default::default()
+            };
+
+            let starts = chunk_starts::<DefaultScanner>(bytes, 1);
+            assert_eq!(starts, [0, 13, 21, 26]);
+
+            let ends = starts[1..].iter().copied().chain([bytes.len()]);
+            let chunks: Vec<_> = starts
+                .iter()
+                .zip(ends)
+                .map(|(&index, end)| {
+                    let start = ChunkStart {
+                        index,
+                        context_stack: Vec::new(),
+                        newline_before: index != 0,
+                        prev: None,
+                    };
+
+                    return lex_chunk::<DefaultScanner>(bytes, start, end, opts);
+                })
+                .collect();
+
+            for index in 1..chunks.len() {
+                let before = &chunks[index - 1];
+                let prev = last_significant(&before.tokens);
+                let chunk = &chunks[index];
+                assert!(guess_was_right(before, chunk, starts[index], prev.as_ref()));
+            }
+        }
+    }
+
+    #[test]
+    fn lex_chunks_agree_on_bad_guesses() {
+        let sources = [
+            // The chunks after the first line break guess a regex.
+            "a = 1\n/ 2 /\ng\n/re/g",
+            "`a\nb${c\n}d\n${ {e:\n1} }`\nx",
+            "/* a\nb */ c\n'd\\\ne'\n// f\ng",
+            "x = <a b='c\nd'>\n  text &amp;\n  {y\n}<b\n/>\n</a>\ny = <T,>\n(z) => z",
+            "'a\nb\n`c\n",
+            "x\n<a>\n",
+        ];
+
+        for source in sources {
+            for recover in [true, false] {
+                let opts = LexOptions {
+                    recover,
+                    jsx: true,
+                    include_comments: true,
+                    ..Default::default()
+                };
+
+                check_chunks_agree(source, opts);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::tokens::ALNUM_MAP;
+
+/// The byte-scanning loops that dominate lexing time. Everything else in the
+/// lexer is shared, so backends only have to agree on these.
+///
+/// All of these start at `index` and return an index in `index..=bytes.len()`,
+/// where `bytes.len()` means the scan ran into the end of the file.
+pub trait Scanner {
+    /// Skips spaces, tabs and line breaks, and returns whether any of them
+    /// were line breaks.
+    fn skip_whitespace(bytes: &[u8], index: usize) -> (usize, bool);
+
+    /// Skips ASCII identifier characters, i.e. the ones `ALNUM_MAP` marks
+    /// with a 1.
+    fn skip_ascii_ident(bytes: &[u8], index: usize) -> usize;
+
+    /// Finds the next `\n` or `\r`.
+    fn find_line_break(bytes: &[u8], index: usize) -> usize;
+
+    /// Finds the end of a block comment, i.e. the index right after its
+    /// `*/`, and whether there's a line break before it. The index is `None`
+    /// if the comment is never closed.
+    fn find_comment_end(bytes: &[u8], index: usize) -> (Option<usize>, bool);
+
+    /// Finds the closing quote of a string, or the line break or end of file
+    /// that ends it early. Escaped quotes and line continuations are skipped.
+    fn find_string_end(bytes: &[u8], index: usize, quote: u8) -> usize;
+
+    /// Finds the closing tick of a template piece, or the `{` of the `${`
+    /// that ends it.
+    fn find_template_end(bytes: &[u8], index: usize) -> usize;
+
+    /// Counts the `\n` bytes.
+    fn count_newlines(bytes: &[u8]) -> usize;
+}
+
+#[cfg(feature = "simd")]
+pub type DefaultScanner = crate::simd::SimdScanner;
+
+#[cfg(not(feature = "simd"))]
+pub type DefaultScanner = ScalarScanner;
+
+/// A byte-at-a-time backend, which works without `portable_simd`.
+pub struct ScalarScanner;
+
+impl Scanner for ScalarScanner {
+    fn skip_whitespace(bytes: &[u8], mut index: usize) -> (usize, bool) {
+        let mut has_newline = false;
+        while let Some(&c) = bytes.get(index) {
+            match c {
+                b' ' | b'\t' => {}
+                b'\n' | b'\r' => has_newline = true,
+                _ => break,
+            }
+
+            index += 1;
+        }
+
+        return (index, has_newline);
+    }
+
+    fn skip_ascii_ident(bytes: &[u8], mut index: usize) -> usize {
+        while let Some(&c) = bytes.get(index) {
+            if ALNUM_MAP[c as usize] != 1 {
+                break;
+            }
+
+            index += 1;
+        }
+
+        return index;
+    }
+
+    fn find_line_break(bytes: &[u8], index: usize) -> usize {
+        let rest = &bytes[index..];
+        let len = rest.iter().position(|&c| c == b'\n' || c == b'\r');
+        return index + len.unwrap_or(rest.len());
+    }
+
+    fn find_comment_end(bytes: &[u8], mut index: usize) -> (Option<usize>, bool) {
+        let mut has_newline = false;
+        while let Some(&c) = bytes.get(index) {
+            index += 1;
+
+            match c {
+                b'\n' | b'\r' => has_newline = true,
+                b'*' if bytes.get(index) == Some(&b'/') => {
+                    return (Some(index + 1), has_newline);
+                }
+                _ => {}
+            }
+        }
+
+        return (None, has_newline);
+    }
+
+    fn find_string_end(bytes: &[u8], mut index: usize, quote: u8) -> usize {
+        while let Some(&c) = bytes.get(index) {
+            match c {
+                b'\n' | b'\r' => return index,
+                b'\\' => match bytes.get(index + 1) {
+                    // The continuation covers both bytes of `\r\n`.
+                    Some(b'\r') if bytes.get(index + 2) == Some(&b'\n') => index += 2,
+                    Some(_) => index += 1,
+                    None => {}
+                },
+                _ if c == quote => return index,
+                _ => {}
+            }
+
+            index += 1;
+        }
+
+        return index;
+    }
+
+    fn find_template_end(bytes: &[u8], mut index: usize) -> usize {
+        while let Some(&c) = bytes.get(index) {
+            match c {
+                b'`' => return index,
+                b'$' if bytes.get(index + 1) == Some(&b'{') => return index + 1,
+                b'\\' => index += 1,
+                _ => {}
+            }
+
+            index += 1;
+        }
+
+        return bytes.len();
+    }
+
+    fn count_newlines(bytes: &[u8]) -> usize {
+        return bytes.iter().filter(|&&c| c == b'\n').count();
+    }
+}
@@ -1,7 +1,8 @@
+use crate::scan::Scanner;
 use std::simd::prelude::*;
 
-pub const fn shiftr_filter(character: u8, shift: u8) -> Simd<u8, 32> {
-    let mut filter = [character; 32];
+pub const fn shiftr_filter<const N: usize>(character: u8, shift: u8) -> Simd<u8, N> {
+    let mut filter = [character; N];
     let mut i = 0;
 
     while i < shift as usize {
@@ -12,24 +13,24 @@ pub const fn shiftr_filter(character: u8, shift: u8) -> Simd<u8, 32> {
     return Simd::from_array(filter);
 }
 
-pub const fn shiftl_filter(character: u8, shift: u8) -> Simd<u8, 32> {
-    let mut filter = [character; 32];
+pub const fn shiftl_filter<const N: usize>(character: u8, shift: u8) -> Simd<u8, N> {
+    let mut filter = [character; N];
 
     let mut i = 0;
 
     while i < shift as usize {
-        filter[32 - i - 1] = 0;
+        filter[N - i - 1] = 0;
         i += 1;
     }
 
     return Simd::from_array(filter);
 }
 
-const fn shiftr_filter_<const CHAR_: u8, const N_: usize>() -> Simd<u8, 32> {
-    let mut filter = [CHAR_; 32];
+const fn shiftr_filter_<const CHAR_: u8, const SHIFT_: usize, const N: usize>() -> Simd<u8, N> {
+    let mut filter = [CHAR_; N];
     let mut i = 0;
 
-    while i < N_ as usize {
+    while i < SHIFT_ {
         filter[i] = 0;
         i += 1;
     }
@@ -45,16 +46,16 @@ const fn shiftr_filter_<const CHAR_: u8, const N_: usize>() -> Simd<u8, 32> {
 // a_mask & b_mask
 //
 #[derive(Clone, Copy)]
-pub struct FilterShiftR<const SHIFT: usize> {
-    filter: Simd<u8, 32>,
+pub struct FilterShiftR<const SHIFT: usize, const N: usize = 32> {
+    filter: Simd<u8, N>,
 }
 
-impl<const SHIFT: usize> FilterShiftR<SHIFT> {
+impl<const SHIFT: usize, const N: usize> FilterShiftR<SHIFT, N> {
     pub const fn new(c: u8) -> Self {
-        let mut filter = [c; 32];
+        let mut filter = [c; N];
         let mut i = 0;
 
-        while i < SHIFT as usize {
+        while i < SHIFT {
             filter[i] = 0;
             i += 1;
         }
@@ -64,15 +65,15 @@ impl<const SHIFT: usize> FilterShiftR<SHIFT> {
         };
     }
 
-    pub fn check_ne(self, t: Simd<u8, 32>) -> Mask<i8, 32> {
+    pub fn check_ne(self, t: Simd<u8, N>) -> Mask<i8, N> {
         return self.check::<false>(t);
     }
 
-    pub fn check_eq(self, t: Simd<u8, 32>) -> Mask<i8, 32> {
+    pub fn check_eq(self, t: Simd<u8, N>) -> Mask<i8, N> {
         return self.check::<true>(t);
     }
 
-    fn check<const EQ: bool>(self, t: Simd<u8, 32>) -> Mask<i8, 32> {
+    fn check<const EQ: bool>(self, t: Simd<u8, N>) -> Mask<i8, N> {
         let rotated = t.rotate_elements_right::<{ SHIFT }>();
 
         let mut mask = if EQ {
@@ -90,3 +91,384 @@ impl<const SHIFT: usize> FilterShiftR<SHIFT> {
         return mask;
     }
 }
+
+// Finds the bytes that are escaped by a backslash, i.e. the ones directly
+// after an odd-length run of backslashes. Runs can cross chunk boundaries,
+// so whether the first byte of the next chunk is escaped is carried over.
+//
+// This is the trick from simdjson (`find_escaped_branchless`): adding the
+// starts of the runs that begin on odd bits to the backslash mask carries
+// each of those runs out to its end, which flips the parity of the bits
+// after it.
+#[derive(Default, Clone, Copy)]
+pub struct EscapeTracker<const N: usize = 32> {
+    prev_escaped: u64,
+}
+
+impl<const N: usize> EscapeTracker<N> {
+    const EVEN_BITS: u64 = 0x5555_5555_5555_5555;
+
+    /// Takes a bitmask of where the backslashes are in the next chunk of `N`
+    /// bytes, and returns a bitmask of the escaped bytes in it.
+    pub fn next(&mut self, backslash: u64) -> u64 {
+        // A backslash that's escaped by the previous chunk doesn't start a run.
+        let backslash = backslash & !self.prev_escaped;
+        let follows_escape = (backslash << 1) | self.prev_escaped;
+
+        let odd_sequence_starts = backslash & !Self::EVEN_BITS & !follows_escape;
+        let (sequences_starting_on_even_bits, overflow) =
+            odd_sequence_starts.overflowing_add(backslash);
+
+        // With fewer than 64 lanes, the carry out of the chunk lands in bit N.
+        self.prev_escaped = match N {
+            64 => overflow as u64,
+            _ => (sequences_starting_on_even_bits >> N) & 1,
+        };
+
+        let invert_mask = sequences_starting_on_even_bits << 1;
+
+        return (Self::EVEN_BITS ^ invert_mask) & follows_escape & lane_mask::<N>();
+    }
+}
+
+const fn lane_mask<const N: usize>() -> u64 {
+    return match N {
+        64 => u64::MAX,
+        _ => (1 << N) - 1,
+    };
+}
+
+/// Scans `N` bytes at a time with `portable_simd`, without caring what the
+/// CPU supports. `SimdScanner` picks the widest one that's fast.
+pub struct SimdLanes<const N: usize>;
+
+impl<const N: usize> SimdLanes<N> {
+    const NEWLINE: Simd<u8, N> = Simd::from_array([b'\n'; N]);
+    const CARRIAGE_RETURN: Simd<u8, N> = Simd::from_array([b'\r'; N]);
+    const BACKSLASH: Simd<u8, N> = Simd::from_array([b'\\'; N]);
+
+    #[inline(always)]
+    fn load(bytes: &[u8], index: usize) -> Simd<u8, N> {
+        return Simd::load_or_default(&bytes[index..]);
+    }
+
+    // Bitmask with the end of the file set, if it's within the next N bytes.
+    #[inline(always)]
+    fn eof_mask(bytes: &[u8], index: usize) -> u64 {
+        let remaining = bytes.len().saturating_sub(index);
+        if remaining < N {
+            return 1 << remaining;
+        }
+
+        return 0;
+    }
+
+    #[inline(always)]
+    fn line_breaks(text: Simd<u8, N>) -> Mask<i8, N> {
+        return text.simd_eq(Self::NEWLINE) | text.simd_eq(Self::CARRIAGE_RETURN);
+    }
+}
+
+impl<const N: usize> Scanner for SimdLanes<N> {
+    #[inline(always)]
+    fn skip_whitespace(bytes: &[u8], mut index: usize) -> (usize, bool) {
+        let tab = Simd::splat(b'\t');
+        let space = Simd::splat(b' ');
+
+        let mut has_newline = false;
+        loop {
+            let text = Self::load(bytes, index);
+
+            let newline_mask = Self::line_breaks(text).to_bitmask();
+            let whitespace_mask =
+                newline_mask | (text.simd_eq(space) | text.simd_eq(tab)).to_bitmask();
+            let end_mask = !whitespace_mask & lane_mask::<N>();
+
+            if end_mask != 0 {
+                let end = end_mask.trailing_zeros();
+                has_newline = has_newline || newline_mask & ((1 << end) - 1) != 0;
+                return (index + end as usize, has_newline);
+            }
+
+            has_newline = has_newline || newline_mask != 0;
+            index += N;
+        }
+    }
+
+    // Compares against the ranges in `ALNUM_MAP` instead of gathering from
+    // it, since a gather is a scalar loop on most CPUs anyways.
+    #[inline(always)]
+    fn skip_ascii_ident(bytes: &[u8], mut index: usize) -> usize {
+        loop {
+            let text = Self::load(bytes, index);
+
+            let lower = text | Simd::splat(0x20);
+            let letters = lower.simd_ge(Simd::splat(b'a')) & lower.simd_le(Simd::splat(b'z'));
+            let digits = text.simd_ge(Simd::splat(b'0')) & text.simd_le(Simd::splat(b'9'));
+            let others = text.simd_eq(Simd::splat(b'_')) | text.simd_eq(Simd::splat(b'$'));
+
+            let ident_mask = (letters | digits | others).to_bitmask();
+            let end_mask = !ident_mask & lane_mask::<N>();
+
+            if end_mask != 0 {
+                return index + end_mask.trailing_zeros() as usize;
+            }
+
+            index += N;
+        }
+    }
+
+    #[inline(always)]
+    fn find_line_break(bytes: &[u8], mut index: usize) -> usize {
+        loop {
+            let text = Self::load(bytes, index);
+            let end_mask = Self::line_breaks(text).to_bitmask() | Self::eof_mask(bytes, index);
+
+            if end_mask != 0 {
+                return index + end_mask.trailing_zeros() as usize;
+            }
+
+            index += N;
+        }
+    }
+
+    #[inline(always)]
+    fn find_comment_end(bytes: &[u8], mut index: usize) -> (Option<usize>, bool) {
+        let star_filter = FilterShiftR::<1, N>::new(b'*');
+        let slash = Simd::splat(b'/');
+
+        let mut has_newline = false;
+        loop {
+            let text = Self::load(bytes, index);
+
+            let star_mask = star_filter.check_eq(text);
+            let slash_mask = text.simd_eq(slash);
+            let newline_mask = Self::line_breaks(text);
+
+            let comment_end_mask = star_mask & slash_mask;
+
+            match comment_end_mask.first_set() {
+                None => has_newline = has_newline || newline_mask.any(),
+                Some(end) => {
+                    let newline_bits = newline_mask.to_bitmask() & ((1 << end) - 1);
+                    has_newline = has_newline || newline_bits != 0;
+                    return (Some(index + end + 1), has_newline);
+                }
+            }
+
+            if Self::eof_mask(bytes, index) != 0 {
+                return (None, has_newline);
+            }
+
+            // The `*` of a `*/` that crosses chunks needs to be seen again.
+            index += N - 1;
+        }
+    }
+
+    #[inline(always)]
+    fn find_string_end(bytes: &[u8], mut index: usize, quote: u8) -> usize {
+        let quote = Simd::splat(quote);
+
+        let mut escapes = EscapeTracker::<N>::default();
+        let mut prev_escaped_cr = 0;
+
+        loop {
+            let text = Self::load(bytes, index);
+
+            let escaped = escapes.next(text.simd_eq(Self::BACKSLASH).to_bitmask());
+            let quotes = text.simd_eq(quote).to_bitmask() & !escaped;
+
+            // An escaped newline is a line continuation, and for `\r\n` the
+            // continuation covers both bytes.
+            let lf = text.simd_eq(Self::NEWLINE).to_bitmask();
+            let cr = text.simd_eq(Self::CARRIAGE_RETURN).to_bitmask();
+            let escaped_cr = cr & escaped;
+            let continued_lf = lf & ((escaped_cr << 1) | prev_escaped_cr);
+            prev_escaped_cr = escaped_cr >> (N - 1);
+
+            let newlines = (lf | cr) & !escaped & !continued_lf;
+
+            let end = quotes | newlines | Self::eof_mask(bytes, index);
+            if end != 0 {
+                return index + end.trailing_zeros() as usize;
+            }
+
+            index += N;
+        }
+    }
+
+    #[inline(always)]
+    fn find_template_end(bytes: &[u8], mut index: usize) -> usize {
+        let tick = Simd::splat(b'`');
+        let dollar = Simd::splat(b'$');
+        let lbrace = Simd::splat(b'{');
+
+        let mut escapes = EscapeTracker::<N>::default();
+        let mut prev_dollar = 0;
+
+        loop {
+            let text = Self::load(bytes, index);
+
+            let escaped = escapes.next(text.simd_eq(Self::BACKSLASH).to_bitmask());
+            let ticks = text.simd_eq(tick).to_bitmask() & !escaped;
+
+            // `${` only opens a substitution when the `$` isn't escaped; an
+            // escaped `{` can't directly follow a `$` anyways.
+            let dollars = text.simd_eq(dollar).to_bitmask() & !escaped;
+            let lbraces = text.simd_eq(lbrace).to_bitmask();
+            let substitutions = lbraces & ((dollars << 1) | prev_dollar);
+            prev_dollar = dollars >> (N - 1);
+
+            let end = ticks | substitutions | Self::eof_mask(bytes, index);
+            if end != 0 {
+                return index + end.trailing_zeros() as usize;
+            }
+
+            index += N;
+        }
+    }
+
+    #[inline(always)]
+    fn count_newlines(bytes: &[u8]) -> usize {
+        let mut count = 0;
+
+        let mut chunks = bytes.chunks_exact(N);
+        for chunk in &mut chunks {
+            let chunk = Simd::<u8, N>::from_slice(chunk);
+            count += chunk.simd_eq(Self::NEWLINE).to_bitmask().count_ones() as usize;
+        }
+
+        let rest = Simd::<u8, N>::load_or_default(chunks.remainder());
+        count += rest.simd_eq(Self::NEWLINE).to_bitmask().count_ones() as usize;
+
+        return count;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimdLevel {
+    /// 16 lanes; SSE2 is part of baseline x86-64.
+    Sse,
+    /// 32 lanes
+    Avx2,
+    /// 64 lanes, using AVX-512BW for the byte compares.
+    Avx512,
+    /// 32 lanes of whatever `portable_simd` does on targets other than
+    /// x86-64.
+    Portable,
+}
+
+impl SimdLevel {
+    pub fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx512bw") {
+                return Self::Avx512;
+            }
+
+            if is_x86_feature_detected!("avx2") {
+                return Self::Avx2;
+            }
+
+            return Self::Sse;
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        return Self::Portable;
+    }
+}
+
+/// Dispatches each scan to `SimdLanes` with the widest vectors the CPU
+/// supports, compiled with the matching target features. Detection is cached
+/// by the standard library, so checking it on every call is cheap.
+pub struct SimdScanner;
+
+// For each method, a copy of the `SimdLanes` version that's compiled with
+// the target features enabled, since inlining into a `target_feature`
+// function is the only way generic code picks them up.
+macro_rules! dispatch {
+    ($( fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty; )*) => {
+        impl Scanner for SimdScanner {
+            $(
+                fn $name($($arg: $ty),*) -> $ret {
+                    #[cfg(target_arch = "x86_64")]
+                    {
+                        #[target_feature(enable = "avx512f,avx512bw")]
+                        unsafe fn avx512($($arg: $ty),*) -> $ret {
+                            return SimdLanes::<64>::$name($($arg),*);
+                        }
+
+                        #[target_feature(enable = "avx2")]
+                        unsafe fn avx2($($arg: $ty),*) -> $ret {
+                            return SimdLanes::<32>::$name($($arg),*);
+                        }
+
+                        // Safety: `SimdLevel::detect` checked for the features.
+                        match SimdLevel::detect() {
+                            SimdLevel::Avx512 => return unsafe { avx512($($arg),*) },
+                            SimdLevel::Avx2 => return unsafe { avx2($($arg),*) },
+                            SimdLevel::Sse => return SimdLanes::<16>::$name($($arg),*),
+                            SimdLevel::Portable => return SimdLanes::<32>::$name($($arg),*),
+                        }
+                    }
+
+                    #[cfg(not(target_arch = "x86_64"))]
+                    return SimdLanes::<32>::$name($($arg),*);
+                }
+            )*
+        }
+    };
+}
+
+dispatch! {
+    fn skip_whitespace(bytes: &[u8], index: usize) -> (usize, bool);
+    fn skip_ascii_ident(bytes: &[u8], index: usize) -> usize;
+    fn find_line_break(bytes: &[u8], index: usize) -> usize;
+    fn find_comment_end(bytes: &[u8], index: usize) -> (Option<usize>, bool);
+    fn find_string_end(bytes: &[u8], index: usize, quote: u8) -> usize;
+    fn find_template_end(bytes: &[u8], index: usize) -> usize;
+    fn count_newlines(bytes: &[u8]) -> usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_escapes<const N: usize>(text: &[u8], expected: &[bool]) {
+        let mut escapes = EscapeTracker::<N>::default();
+        for (chunk_index, chunk) in text.chunks(N).enumerate() {
+            let backslash = Simd::<u8, N>::load_or_default(chunk)
+                .simd_eq(Simd::splat(b'\\'))
+                .to_bitmask();
+            let escaped = escapes.next(backslash);
+
+            for i in 0..chunk.len() {
+                let index = chunk_index * N + i;
+                assert_eq!(escaped & (1 << i) != 0, expected[index], "{} {}", N, index);
+            }
+        }
+    }
+
+    #[test]
+    fn escape_tracker_lanes() {
+        // Backslash runs of lengths 1 through 9, so they end at every parity
+        // and cross chunk boundaries in every way.
+        let mut text = Vec::new();
+        for len in 1..10 {
+            text.extend(b"\\".repeat(len));
+            text.push(b'a');
+        }
+        let text = text.repeat(8);
+
+        let mut expected = Vec::new();
+        let mut run = 0;
+        for &c in &text {
+            expected.push(run % 2 == 1);
+            run = if c == b'\\' { run + 1 } else { 0 };
+        }
+
+        check_escapes::<16>(&text, &expected);
+        check_escapes::<32>(&text, &expected);
+        check_escapes::<64>(&text, &expected);
+    }
+}
@@ -1,14 +1,19 @@
 use soa_derive::*;
-use std::collections::HashMap;
 use strum::*;
 
 use crate::util::Symbols;
 
-#[derive(Debug, StructOfArray)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, StructOfArray)]
 pub struct Token {
     pub kind: TokenKind,
-    pub text_index: usize,
+    /// Byte offsets of the token's text, `start..end`.
+    pub start: u32,
+    pub end: u32,
+    /// Kind-specific payload, e.g. the symbol id for a `Word`.
     pub extra: u32,
+    /// Whether there's a line terminator between this token and the previous
+    /// non-trivia token. This is what the parser uses for ASI.
+    pub newline_before: bool,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, IntoStaticStr)]
@@ -26,22 +31,55 @@ pub enum TokenKind {
     Sub,
     Div,
     Mult,
+    Mod,
+    Exp,
 
     BoolAnd,
     BoolOr,
+    Nullish,
+    Not,
 
     BinAnd,
     BinOr,
     BinXor,
+    BinNot,
+    Shl,
+    Shr,
+    UShr,
+
+    AddEq,
+    SubEq,
+    DivEq,
+    MultEq,
+    ModEq,
+    ExpEq,
+    BoolAndEq,
+    BoolOrEq,
+    NullishEq,
+    BinAndEq,
+    BinOrEq,
+    BinXorEq,
+    ShlEq,
+    ShrEq,
+    UShrEq,
 
     EqEq,
     EqEqEq,
     Neq,
+    NeqEq,
     Geq,
     Leq,
     Gt,
     Lt,
 
+    Question,
+    OptChain,
+    Arrow,
+    At,
+    Hash,
+    /// `#name` in a class. Its extra is the symbol for the name, `#` included.
+    PrivateName,
+
     LParen,
     RParen,
     LBracket,
@@ -53,6 +91,8 @@ pub enum TokenKind {
 
     LineComment,
     Comment,
+    /// A `#!` line at the start of the file.
+    Hashbang,
     Whitespace,
     Unknown,
 
@@ -60,6 +100,7 @@ pub enum TokenKind {
     Eq,
 
     String,
+    Regex,
     StrTemplate,
     StrTemplateBegin,
     StrTemplateMid,
@@ -72,6 +113,16 @@ pub enum TokenKind {
     BigInt,
 
     Word,
+
+    /// Text between JSX tags. Its extra is the text's id in
+    /// `Symbols::strings`, with character references decoded.
+    JsxText,
+    /// A JSX attribute string. These have character references instead of
+    /// escapes, but get stored the same way as `JsxText`.
+    JsxString,
+    /// A JSX tag or attribute name, which can have `-` in it. Its extra is
+    /// the symbol for the name.
+    JsxIdent,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, EnumIter, IntoStaticStr)]
@@ -120,6 +171,59 @@ pub enum Key {
     Yield,
 }
 
+/// TypeScript's contextual keywords. These are lexed as `Word`s, so that e.g.
+/// `let type = 1` still works, but `Symbols::new` interns them before anything
+/// else, so each one's symbol id is just its discriminant and the parser can
+/// check for them without comparing strings.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, EnumCount, EnumIter, FromRepr, IntoStaticStr)]
+#[repr(u8)]
+pub enum CtxKey {
+    Type = 1,
+    Interface,
+    Namespace,
+    Module,
+    Declare,
+    Abstract,
+    Readonly,
+    Keyof,
+    Infer,
+    Is,
+    Asserts,
+    Satisfies,
+    Implements,
+    Private,
+    Public,
+    Protected,
+    Static,
+    Get,
+    Set,
+    Of,
+    From,
+    Unique,
+    Override,
+    Accessor,
+}
+
+impl CtxKey {
+    pub const fn symbol(self) -> u32 {
+        return self as u32;
+    }
+
+    pub fn from_symbol(symbol: u32) -> Option<Self> {
+        return Self::from_repr(u8::try_from(symbol).ok()?);
+    }
+}
+
+impl Key {
+    /// Keywords are lexed as `Key`s without a symbol, but `Symbols::new`
+    /// interns them right after the contextual keywords, so that property
+    /// names like `a.if` still have one.
+    pub const fn symbol(self) -> u32 {
+        // Symbol 0 is never used, and the contextual keywords come after it.
+        return 1 + CtxKey::COUNT as u32 + (self as u32 - Key::As as u32);
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct CharAttrs {
     alpha: bool,
@@ -128,28 +232,161 @@ pub struct CharAttrs {
     spec: bool,
 }
 
-lazy_static::lazy_static! {
-    pub static ref KEY_HASH_MAP : HashMap<Vec<u8>, Key> = {
-        let mut map = HashMap::with_capacity(64);
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Keyword {
+    Key(Key),
+    Ctx(CtxKey),
+}
 
-        // TODO: This is dumb. We can keep it for now, but it's dumb, lets use
-        // something real in the future.
-        for keyword in Key::iter() {
-            let text: &'static str = keyword.into();
-            let lowercase = text.to_ascii_lowercase();
-            let bytes = lowercase.as_bytes().to_owned();
+// Every keyword and contextual keyword. `keyword_hash` only looks at the first
+// two bytes, the last byte and the length, which happen to be unique for all
+// of these.
+const KEYWORDS: [(&[u8], Keyword); 65] = {
+    use CtxKey as C;
+    use Key as K;
+    use Keyword::{Ctx, Key as Kw};
+
+    [
+        (b"as", Kw(K::As)),
+        (b"async", Kw(K::Async)),
+        (b"await", Kw(K::Await)),
+        (b"break", Kw(K::Break)),
+        (b"case", Kw(K::Case)),
+        (b"catch", Kw(K::Catch)),
+        (b"class", Kw(K::Class)),
+        (b"const", Kw(K::Const)),
+        (b"continue", Kw(K::Continue)),
+        (b"debugger", Kw(K::Debugger)),
+        (b"default", Kw(K::Default)),
+        (b"delete", Kw(K::Delete)),
+        (b"do", Kw(K::Do)),
+        (b"else", Kw(K::Else)),
+        (b"enum", Kw(K::Enum)),
+        (b"export", Kw(K::Export)),
+        (b"extends", Kw(K::Extends)),
+        (b"false", Kw(K::False)),
+        (b"finally", Kw(K::Finally)),
+        (b"for", Kw(K::For)),
+        (b"function", Kw(K::Function)),
+        (b"if", Kw(K::If)),
+        (b"import", Kw(K::Import)),
+        (b"in", Kw(K::In)),
+        (b"instanceof", Kw(K::Instanceof)),
+        (b"let", Kw(K::Let)),
+        (b"new", Kw(K::New)),
+        (b"null", Kw(K::Null)),
+        (b"return", Kw(K::Return)),
+        (b"super", Kw(K::Super)),
+        (b"switch", Kw(K::Switch)),
+        (b"this", Kw(K::This)),
+        (b"throw", Kw(K::Throw)),
+        (b"true", Kw(K::True)),
+        (b"try", Kw(K::Try)),
+        (b"typeof", Kw(K::Typeof)),
+        (b"var", Kw(K::Var)),
+        (b"void", Kw(K::Void)),
+        (b"while", Kw(K::While)),
+        (b"with", Kw(K::With)),
+        (b"yield", Kw(K::Yield)),
+        (b"type", Ctx(C::Type)),
+        (b"interface", Ctx(C::Interface)),
+        (b"namespace", Ctx(C::Namespace)),
+        (b"module", Ctx(C::Module)),
+        (b"declare", Ctx(C::Declare)),
+        (b"abstract", Ctx(C::Abstract)),
+        (b"readonly", Ctx(C::Readonly)),
+        (b"keyof", Ctx(C::Keyof)),
+        (b"infer", Ctx(C::Infer)),
+        (b"is", Ctx(C::Is)),
+        (b"asserts", Ctx(C::Asserts)),
+        (b"satisfies", Ctx(C::Satisfies)),
+        (b"implements", Ctx(C::Implements)),
+        (b"private", Ctx(C::Private)),
+        (b"public", Ctx(C::Public)),
+        (b"protected", Ctx(C::Protected)),
+        (b"static", Ctx(C::Static)),
+        (b"get", Ctx(C::Get)),
+        (b"set", Ctx(C::Set)),
+        (b"of", Ctx(C::Of)),
+        (b"from", Ctx(C::From)),
+        (b"unique", Ctx(C::Unique)),
+        (b"override", Ctx(C::Override)),
+        (b"accessor", Ctx(C::Accessor)),
+    ]
+};
+
+const KEYWORD_MIN_LEN: usize = 2;
+const KEYWORD_MAX_LEN: usize = 10;
+const KEYWORD_TABLE_BITS: u32 = 8;
+
+const fn keyword_hash(word: &[u8], seed: u32) -> usize {
+    let len = word.len();
+    let signature = (word[0] as u32)
+        | ((word[1] as u32) << 8)
+        | ((word[len - 1] as u32) << 16)
+        | ((len as u32) << 24);
+
+    return (signature.wrapping_mul(seed) >> (32 - KEYWORD_TABLE_BITS)) as usize;
+}
+
+// Slot `i` holds the index into `KEYWORDS` plus 1, or 0 if it's empty.
+const fn keyword_table(seed: u32) -> Option<[u8; 1 << KEYWORD_TABLE_BITS]> {
+    let mut table = [0; 1 << KEYWORD_TABLE_BITS];
 
-            map.insert(bytes, keyword);
+    let mut i = 0;
+    while i < KEYWORDS.len() {
+        let slot = keyword_hash(KEYWORDS[i].0, seed);
+        if table[slot] != 0 {
+            return None;
         }
 
-        map
-    };
+        table[slot] = i as u8 + 1;
+        i += 1;
+    }
+
+    return Some(table);
+}
+
+// Brute-force search for a multiplier that doesn't collide. This takes a few
+// hundred tries, which is fine for the compiler to do once.
+const KEYWORD_SEED: u32 = {
+    let mut seed: u32 = 0x9E37_79B1;
+    while keyword_table(seed).is_none() {
+        seed = seed.wrapping_add(2);
+    }
+
+    seed
+};
+
+static KEYWORD_TABLE: [u8; 1 << KEYWORD_TABLE_BITS] = match keyword_table(KEYWORD_SEED) {
+    Some(table) => table,
+    None => panic!("keyword seed has collisions"),
+};
 
+/// Classifies a word as a keyword or contextual keyword, with one hash and
+/// one compare.
+pub fn lookup_keyword(word: &[u8]) -> Option<Keyword> {
+    if word.len() < KEYWORD_MIN_LEN || word.len() > KEYWORD_MAX_LEN {
+        return None;
+    }
+
+    let slot = KEYWORD_TABLE[keyword_hash(word, KEYWORD_SEED)];
+    let (text, keyword) = KEYWORDS.get((slot as usize).wrapping_sub(1))?;
+    if *text != word {
+        return None;
+    }
+
+    return Some(*keyword);
+}
+
+lazy_static::lazy_static! {
+    // 1 for ASCII identifier characters, 2 for bytes that might start a
+    // non-ASCII identifier character and need to be decoded to check.
     pub static ref ALNUM_MAP : [u8; 256] = {
         let mut attrs = [0; 256];
 
-        for i in 128..256 {
-            attrs[i] = 1;
+        for attr in &mut attrs[128..] {
+            *attr = 2;
         }
 
         for i in b'a'..=b'z' {
@@ -165,6 +402,7 @@ lazy_static::lazy_static! {
         }
 
         attrs[b'_' as usize] = 1;
+        attrs[b'$' as usize] = 1;
 
         attrs
     };
@@ -179,6 +417,13 @@ const fn check_tokenkind_size() {
 const _: () = check_tokenkind_size();
 
 impl TokenKind {
+    pub fn is_trivia(&self) -> bool {
+        return matches!(
+            self,
+            Self::Whitespace | Self::Comment | Self::LineComment | Self::Hashbang
+        );
+    }
+
     pub fn len(&self) -> Option<usize> {
         match self {
             Self::Key(key) => {
@@ -193,28 +438,61 @@ impl TokenKind {
             Self::Spread => return Some(3),
 
             Self::Comma => return Some(1),
+            Self::Semicolon => return Some(1),
+            Self::Colon => return Some(1),
 
             Self::Add => return Some(1),
             Self::Sub => return Some(1),
             Self::Div => return Some(1),
             Self::Mult => return Some(1),
+            Self::Mod => return Some(1),
+            Self::Exp => return Some(2),
 
             Self::BoolAnd => return Some(2),
             Self::BoolOr => return Some(2),
+            Self::Nullish => return Some(2),
+            Self::Not => return Some(1),
 
             Self::BinAnd => return Some(1),
             Self::BinOr => return Some(1),
             Self::BinXor => return Some(1),
+            Self::BinNot => return Some(1),
+            Self::Shl => return Some(2),
+            Self::Shr => return Some(2),
+            Self::UShr => return Some(3),
+
+            Self::AddEq => return Some(2),
+            Self::SubEq => return Some(2),
+            Self::DivEq => return Some(2),
+            Self::MultEq => return Some(2),
+            Self::ModEq => return Some(2),
+            Self::ExpEq => return Some(3),
+            Self::BoolAndEq => return Some(3),
+            Self::BoolOrEq => return Some(3),
+            Self::NullishEq => return Some(3),
+            Self::BinAndEq => return Some(2),
+            Self::BinOrEq => return Some(2),
+            Self::BinXorEq => return Some(2),
+            Self::ShlEq => return Some(3),
+            Self::ShrEq => return Some(3),
+            Self::UShrEq => return Some(4),
 
             Self::Eq => return Some(1),
             Self::EqEq => return Some(2),
             Self::EqEqEq => return Some(3),
             Self::Neq => return Some(2),
+            Self::NeqEq => return Some(3),
             Self::Geq => return Some(2),
             Self::Leq => return Some(2),
             Self::Gt => return Some(1),
             Self::Lt => return Some(1),
 
+            Self::Question => return Some(1),
+            Self::OptChain => return Some(2),
+            Self::Arrow => return Some(2),
+            Self::At => return Some(1),
+            Self::Hash => return Some(1),
+
             Self::LParen => return Some(1),
             Self::RParen => return Some(1),
             Self::LBracket => return Some(1),
@@ -234,7 +512,7 @@ impl TokenVec {
             let token_name: &'static str = (*token.kind).into();
 
             match token.kind {
-                TokenKind::Word => {
+                TokenKind::Word | TokenKind::PrivateName | TokenKind::JsxIdent => {
                     output.push_str(token_name);
 
                     let word_name = symbols.to_str(*token.extra).unwrap();
@@ -262,3 +540,90 @@ impl TokenVec {
         return output;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "simd")]
+    use crate::util::*;
+    #[cfg(feature = "simd")]
+    use std::collections::HashMap;
+
+    const WORDS: &[&str] = &[
+        "let",
+        "x",
+        "function",
+        "foo",
+        "return",
+        "types",
+        "instanceof",
+        "of",
+        "a",
+        "type",
+        "interfaces",
+        "yieldy",
+        "$",
+        "_private",
+        "await",
+        "elseif",
+        "from",
+        "constructor",
+    ];
+
+    #[test]
+    fn keyword_lookup() {
+        for key in Key::iter() {
+            let name: &'static str = key.into();
+            let name = name.to_ascii_lowercase();
+            assert_eq!(lookup_keyword(name.as_bytes()), Some(Keyword::Key(key)));
+        }
+
+        for key in CtxKey::iter() {
+            let name: &'static str = key.into();
+            let name = name.to_ascii_lowercase();
+            assert_eq!(lookup_keyword(name.as_bytes()), Some(Keyword::Ctx(key)));
+        }
+
+        let not_keywords = [
+            "",
+            "a",
+            "x",
+            "Let",
+            "lets",
+            "ass",
+            "elseif",
+            "constructor",
+            "yieldy",
+        ];
+        for word in not_keywords {
+            assert_eq!(lookup_keyword(word.as_bytes()), None, "{}", word);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn keyword_perfect_hash_bench(bencher: &mut Bencher) {
+        bencher.iter(|| {
+            for word in WORDS {
+                black_box(lookup_keyword(black_box(word.as_bytes())));
+            }
+        });
+    }
+
+    #[cfg(feature = "simd")]
+    #[bench]
+    fn keyword_hash_map_bench(bencher: &mut Bencher) {
+        // The map that `lookup_keyword` replaced.
+        let mut map = HashMap::with_capacity(64);
+        for keyword in Key::iter() {
+            let text: &'static str = keyword.into();
+            map.insert(text.to_ascii_lowercase().into_bytes(), keyword);
+        }
+
+        bencher.iter(|| {
+            for word in WORDS {
+                black_box(map.get(black_box(word.as_bytes())));
+            }
+        });
+    }
+}
@@ -1,17 +1,42 @@
 use std::collections::HashMap;
+use strum::IntoEnumIterator;
+
+use crate::literals::{NumberTable, StringTable};
+use crate::tokens::{CtxKey, Key};
 
 // TODO: Do something less stupid here.
 pub struct Symbols {
     pub to_symbol: HashMap<String, u32>,
     pub to_name: Vec<String>,
+    /// Values of string literals and template pieces.
+    pub strings: StringTable,
+    /// Values of numeric literals.
+    pub numbers: NumberTable,
 }
 
 impl Symbols {
     pub fn new() -> Self {
-        return Self {
+        let mut symbols = Self {
             to_symbol: HashMap::new(),
             to_name: Vec::new(),
+            strings: StringTable::default(),
+            numbers: NumberTable::default(),
         };
+
+        // Contextual keywords get the first ids; see `CtxKey`.
+        for key in CtxKey::iter() {
+            let name: &'static str = key.into();
+            let id = symbols.add_str(name.to_ascii_lowercase());
+            debug_assert_eq!(id, key.symbol());
+        }
+
+        for key in Key::iter() {
+            let name: &'static str = key.into();
+            let id = symbols.add_str(name.to_ascii_lowercase());
+            debug_assert_eq!(id, key.symbol());
+        }
+
+        return symbols;
     }
 
     pub fn add_str<T: AsRef<str> + Into<String>>(&mut self, s: T) -> u32 {
@@ -28,14 +53,16 @@ impl Symbols {
 
     pub fn from_str(&self, s: &str) -> Option<u32> {
         if let Some(id) = self.to_symbol.get(s) {
-            return Some((*id).into());
+            return Some(*id);
         }
 
         return None;
     }
 
     pub fn to_str(&self, id: u32) -> Option<&str> {
-        return self.to_name.get(id as usize).map(|a| -> &str { &*a });
+        // Ids start at 1, so that 0 can mean "no symbol".
+        let index = (id as usize).checked_sub(1)?;
+        return self.to_name.get(index).map(|a| a.as_str());
     }
 }
 
@@ -43,8 +70,10 @@ impl Symbols {
 pub use tests::*;
 #[cfg(test)]
 pub mod tests {
+    #[cfg(feature = "simd")]
     extern crate test;
 
+    #[cfg(feature = "simd")]
     pub use test::{black_box, Bencher};
     pub use test_generator::test_resources;
     pub use yaml_rust::YamlLoader;
@@ -52,20 +81,14 @@ pub mod tests {
     pub fn extract_yaml(source: &str) -> Option<yaml_rust::Yaml> {
         let mut yaml_text = "";
         for item in source.split("/*---") {
-            if item == "" {
+            if item.is_empty() {
                 continue;
             }
             yaml_text = item;
             break;
         }
 
-        let mut yaml_text_2 = "";
-        for item in yaml_text.split("---*/") {
-            yaml_text_2 = item;
-            break;
-        }
-
-        let yaml_text = yaml_text_2;
+        let yaml_text = yaml_text.split("---*/").next().unwrap_or("");
         let mut docs = YamlLoader::load_from_str(yaml_text).unwrap();
         return docs.pop();
     }
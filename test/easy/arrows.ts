@@ -0,0 +1,9 @@
+/*---
+tokens: Word,Eq,LParen,Word,Colon,Word,Comma,Word,RParen,Colon,Word,Arrow,Word,Add,Word,Semicolon,Word,LParen,Word,Arrow,LBrace,Key(Return),Word,Semicolon,RBrace,RParen,Semicolon
+ast: ExprWord,ExprArrowIntro,ExprParamsIntro,PatWord,TypeRef,PatParam,PatWord,PatParam,ExprParams,TypeRef,ExprWord,ExprWord,ExprAdd,ExprArrow,ExprAssign,ExprWord,ExprArrowIntro,ExprParamsIntro,PatWord,PatParam,ExprParams,StmtBlockIntro,ExprWord,StmtReturn,StmtBlock,ExprArrow,ExprCall
+---*/
+
+add = (a: number, b): number => a + b;
+f(x => {
+  return x;
+});
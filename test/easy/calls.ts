@@ -0,0 +1,7 @@
+/*---
+tokens: Key(New),Word,Dot,Word,LParen,Word,Comma,Spread,Word,RParen,OptChain,LBracket,Number,RBracket,Semicolon,Word,StrTemplateBegin,Word,StrTemplateEnd,Semicolon
+ast: ExprWord,ExprMember,ExprWord,ExprWord,ExprSpread,ExprNew,ExprOptional,ExprNumber,ExprIndex,ExprOptChain,ExprWord,ExprTemplateIntro,ExprWord,ExprTemplatePiece,ExprTemplate,ExprTaggedTemplate
+---*/
+
+new a.b(c, ...d)?.[0];
+tag`x${y}z`;
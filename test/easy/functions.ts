@@ -0,0 +1,9 @@
+/*---
+tokens: Key(Function),Word,LParen,Word,Colon,Word,Comma,Spread,Word,RParen,Colon,Word,LBrace,Key(Return),Word,Semicolon,RBrace,Word,Eq,Key(Function),LParen,RParen,LBrace,RBrace,Semicolon
+ast: ExprFunctionIntro,ExprParamsIntro,PatWord,TypeRef,PatParam,PatWord,PatRest,PatParam,ExprParams,TypeRef,StmtBlockIntro,ExprWord,StmtReturn,StmtBlock,ExprFunction,ExprWord,ExprFunctionIntro,ExprParamsIntro,ExprParams,StmtBlockIntro,StmtBlock,ExprFunction,ExprAssign
+---*/
+
+function f(a: number, ...b): string {
+  return a;
+}
+g = function () {};
@@ -0,0 +1,12 @@
+/*---
+tokens: Word,Semicolon,Word,Semicolon,Word,Semicolon,Word,Semicolon,Word,Semicolon,Word,Semicolon,Word,Semicolon,Word,Dot,Word,Semicolon
+---*/
+
+$;
+$el;
+_$_;
+café;
+日本語;
+abc;
+a\u{62}c;
+℮x.ℼ;
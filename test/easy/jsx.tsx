@@ -0,0 +1,18 @@
+/*---
+tokens: Key(Const),Word,Eq,Lt,JsxIdent,JsxIdent,Eq,JsxString,JsxIdent,Eq,LBrace,Word,Dot,Word,RBrace,Gt,
+  JsxText,Lt,JsxIdent,Dot,JsxIdent,LBrace,Spread,Word,RBrace,Div,Gt,
+  JsxText,LBrace,Word,Lt,Number,BoolAnd,Lt,JsxIdent,Gt,LBrace,StrTemplateBegin,Word,StrTemplateEnd,RBrace,Lt,Div,JsxIdent,Gt,RBrace,
+  JsxText,Lt,Gt,Lt,JsxIdent,Colon,JsxIdent,Div,Gt,Lt,Div,Gt,JsxText,Lt,Div,JsxIdent,Gt,Semicolon,
+  Key(Const),Word,Eq,Lt,Word,Comma,Gt,LParen,Word,RParen,Arrow,Word,Semicolon,
+  Key(Const),Word,Eq,Lt,Word,Key(Extends),Word,Gt,LParen,Word,RParen,Arrow,Word,Semicolon,
+  Key(Const),Word,Eq,Lt,JsxIdent,JsxIdent,Eq,JsxString,Gt,Lt,Div,JsxIdent,Gt,Semicolon
+---*/
+
+const el = <div data-id="a &amp; b" class={styles.root}>
+  Hello, <Foo.Bar {...props} />
+  {count < 10 && <b>{`${count}`}</b>}
+  &copy; <><svg:path /></> done
+</div>;
+const id = <T,>(x) => x;
+const ext = <T extends object>(x) => x;
+const attr = <a extends="b"></a>;
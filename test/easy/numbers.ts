@@ -0,0 +1,24 @@
+/*---
+tokens: Number,Semicolon,Number,Semicolon,Number,Semicolon,Number,Semicolon,Number,Semicolon,Number,Semicolon,Number,Semicolon,Number,Semicolon,HexNumber,Semicolon,HexNumber,Semicolon,OctNumber,Semicolon,BinNumber,Semicolon,OctNumber,Semicolon,Number,Semicolon,BigInt,Semicolon,BigInt,Semicolon,BigInt,Semicolon,BigInt,Semicolon,Number,Dot,Word,Semicolon,Spread,Word,Semicolon
+---*/
+
+0;
+1_000_000;
+1.5;
+.5;
+1e10;
+1.5E-3;
+2e+8;
+0.1_1;
+0xFF;
+0X1234_abcd;
+0o17;
+0b1010_1010;
+017;
+019.5;
+0n;
+12n;
+0xFFn;
+0b1n;
+1..toString;
+...rest;
@@ -0,0 +1,7 @@
+/*---
+tokens: Word,Add,Word,Mult,Word,Exp,Word,Nullish,Word,Semicolon,Word,Eq,Not,Word,Question,Number,Colon,Sub,Word,PlusPlus,Comma,Word,Semicolon
+ast: ExprWord,ExprWord,ExprWord,ExprWord,ExprExp,ExprMult,ExprAdd,ExprWord,ExprNullish,ExprWord,ExprWord,ExprNot,ExprNumber,ExprWord,ExprPostIncr,ExprNegative,ExprConditional,ExprAssign,ExprWord,ExprComma
+---*/
+
+a + b * c ** d ?? e;
+x = !y ? 1 : -z++, w;
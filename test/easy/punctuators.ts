@@ -0,0 +1,27 @@
+/*---
+tokens: Word,Eq,Word,Add,Word,Sub,Word,Mult,Word,Div,Word,Mod,Word,Exp,Word,Semicolon,
+  Word,AddEq,Word,SubEq,Word,MultEq,Word,DivEq,Word,ModEq,Word,ExpEq,Word,Semicolon,
+  Word,ShlEq,Word,ShrEq,Word,UShrEq,Word,BinAndEq,Word,BinOrEq,Word,BinXorEq,Word,Semicolon,
+  Word,BoolAndEq,Word,BoolOrEq,Word,NullishEq,Word,Semicolon,
+  Word,EqEq,Word,EqEqEq,Word,Neq,Word,NeqEq,Word,Lt,Word,Gt,Word,Leq,Word,Geq,Word,Semicolon,
+  Word,Shl,Word,Shr,Word,UShr,Word,BinAnd,Word,BinOr,Word,BinXor,BinNot,Word,Semicolon,
+  Word,BoolAnd,Not,Word,BoolOr,Word,Nullish,Word,Semicolon,
+  Word,OptChain,Word,OptChain,LBracket,Word,RBracket,Semicolon,
+  Word,Question,Number,Colon,Number,Semicolon,
+  LParen,Word,Comma,Word,RParen,Arrow,Word,PlusPlus,Semicolon,
+  MinusMinus,Word,Semicolon,
+  At,Word,PrivateName,Semicolon
+---*/
+
+a = b + c - d * e / f % g ** h;
+a += b -= c *= d /= e %= f **= g;
+a <<= b >>= c >>>= d &= e |= f ^= g;
+a &&= b ||= c ??= d;
+a == b === c != d !== e < f > g <= h >= i;
+a << b >> c >>> d & e | f ^ ~g;
+a && !b || c ?? d;
+a?.b?.[c];
+a?.5:.5;
+(a, b) => c++;
+--d;
+@dec #priv;
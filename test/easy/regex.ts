@@ -0,0 +1,19 @@
+/*---
+tokens: Word,Dot,Word,LParen,Regex,Comma,String,RParen,Semicolon,
+  Word,Eq,Word,Div,Word,Div,Word,Semicolon,
+  Word,Eq,Regex,Semicolon,
+  Word,Eq,Regex,Dot,Word,LParen,Word,RParen,Semicolon,
+  Key(Return),Regex,Semicolon,
+  Word,DivEq,Number,Semicolon,
+  Word,Eq,LParen,Word,RParen,Div,Number,Semicolon,
+  Word,Eq,Regex,Semicolon
+---*/
+
+x.replace(/a+/g, "");
+a = b / c / d;
+r = /[/\]]+\//;
+r = /=\d{2}/u.test(s);
+return /re/;
+n /= 2;
+n = (a) / 2;
+r = /a\/[\\]/dgimsuy;
@@ -0,0 +1,11 @@
+/*---
+tokens: StrTemplateBegin,LBrace,Word,Colon,Number,RBrace,Dot,Word,StrTemplateEnd,Semicolon,
+  StrTemplateBegin,Word,Arrow,LBrace,Key(Return),StrTemplateBegin,LBrace,Word,RBrace,Dot,Word,StrTemplateEnd,Semicolon,RBrace,StrTemplateMid,Word,StrTemplateEnd,Semicolon,
+  StrTemplateBegin,StrTemplateBegin,StrTemplateBegin,StrTemplate,StrTemplateEnd,StrTemplateMid,LBrace,RBrace,StrTemplateEnd,StrTemplateEnd,Semicolon,
+  LBrace,StrTemplateBegin,Word,StrTemplateEnd,RBrace
+---*/
+
+`${ {a: 1}.a }`;
+`a${ x => { return `b${ {c}.c }d`; } }e${f}g`;
+`1${`2${`3${`4`}5`}6${{}}7`}8`;
+{ `${x}` }
@@ -1,5 +1,6 @@
 /*---
 tokens: Key(Let),Word,Semicolon,Key(Let),Word,Semicolon
+ast: PatWord,StmtVarDecl,StmtLet,PatWord,StmtVarDecl,StmtLet
 ---*/
 
 let a;